@@ -0,0 +1,336 @@
+use std::{collections::HashSet, path::Path};
+
+use anyhow::{format_err, Result};
+use serde::Deserialize;
+use serum_dex::{instruction::NewOrderInstructionV3, matching::Side};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    instruction::Instruction,
+    message::Message,
+    packet::PACKET_DATA_SIZE,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+    transaction::Transaction,
+};
+
+use crate::{new_order_instruction, read_file, MarketPubkeys, OrderKind, OrderSide};
+
+/// A single order of a batch file.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BatchOrder {
+    pub side: OrderSide,
+    pub size: u64,
+    pub rate: u64,
+    #[serde(default, rename = "type")]
+    pub order_type: OrderKind,
+    #[serde(default)]
+    pub client_id: Option<u64>,
+}
+
+/// Reads orders from a JSON file holding an array of orders, or from a CSV file
+/// with a `side,size,rate,type,client_id` header where `type` and `client_id` are optional.
+pub fn read_batch_file(path: &str) -> Result<Vec<BatchOrder>> {
+    let content = read_file(path).map_err(|err| format_err!(err))?;
+
+    let is_json = Path::new(path)
+        .extension()
+        .map(|extension| extension.eq_ignore_ascii_case("json"))
+        .unwrap_or(false);
+
+    if is_json {
+        parse_batch_json(&content)
+    } else {
+        parse_batch_csv(&content)
+    }
+}
+
+pub fn parse_batch_json(content: &str) -> Result<Vec<BatchOrder>> {
+    check_batch_client_ids(serde_json::from_str(content)?)
+}
+
+pub fn parse_batch_csv(content: &str) -> Result<Vec<BatchOrder>> {
+    let mut lines = content
+        .lines()
+        .map(str::trim)
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+    let header: Vec<String> = match lines.next() {
+        Some((_, line)) => line.split(',').map(|column| column.trim().to_lowercase()).collect(),
+        None => return Ok(Vec::new()),
+    };
+
+    let column = |name: &str| header.iter().position(|column| column == name);
+    let side_column = column("side").ok_or_else(|| format_err!("missing side column"))?;
+    let size_column = column("size").ok_or_else(|| format_err!("missing size column"))?;
+    let rate_column = column("rate").ok_or_else(|| format_err!("missing rate column"))?;
+    let type_column = column("type");
+    let client_id_column = column("client_id");
+
+    let mut orders = Vec::new();
+    for (index, line) in lines {
+        let values: Vec<&str> = line.split(',').map(str::trim).collect();
+        let value = |column: Option<usize>| {
+            column
+                .and_then(|column| values.get(column).copied())
+                .filter(|value| !value.is_empty())
+        };
+
+        let parse_error = |field: &str| format_err!("line {}: invalid {}", index + 1, field);
+
+        let side = match value(Some(side_column)) {
+            Some("lend") => OrderSide::Lend,
+            Some("borrow") => OrderSide::Borrow,
+            _ => return Err(parse_error("side, expected lend or borrow")),
+        };
+
+        let size = value(Some(size_column))
+            .and_then(|size| size.parse::<u64>().ok())
+            .ok_or_else(|| parse_error("size"))?;
+
+        let rate = value(Some(rate_column))
+            .and_then(|rate| rate.parse::<u64>().ok())
+            .ok_or_else(|| parse_error("rate"))?;
+
+        let order_type = match value(type_column) {
+            Some(order_type) => order_type
+                .parse::<OrderKind>()
+                .map_err(|_| parse_error("type, expected limit, ioc or post-only"))?,
+            None => OrderKind::Limit,
+        };
+
+        let client_id = match value(client_id_column) {
+            Some(client_id) => Some(
+                client_id
+                    .parse::<u64>()
+                    .map_err(|_| parse_error("client_id"))?,
+            ),
+            None => None,
+        };
+
+        orders.push(BatchOrder {
+            side,
+            size,
+            rate,
+            order_type,
+            client_id,
+        });
+    }
+
+    check_batch_client_ids(orders)
+}
+
+/// Rejects a batch in which two orders ask for the same client order id before any of
+/// them is sent.
+fn check_batch_client_ids(orders: Vec<BatchOrder>) -> Result<Vec<BatchOrder>> {
+    let mut client_ids = HashSet::new();
+
+    for client_id in orders.iter().filter_map(|order| order.client_id) {
+        if !client_ids.insert(client_id) {
+            return Err(format_err!(
+                "client order id {} is used by more than one order",
+                client_id
+            ));
+        }
+    }
+
+    Ok(orders)
+}
+
+/// Maximum number of orders sent in one transaction. Every order can match against
+/// resting orders, which costs compute units, so a transaction filled up to the packet
+/// size could run out of its compute budget.
+pub const MAX_ORDERS_PER_TRANSACTION: usize = 5;
+
+/// Groups instructions into as few transactions as possible without exceeding the packet
+/// size or `max_instructions` instructions per transaction.
+pub fn pack_instructions(
+    payer: &Pubkey,
+    instructions: Vec<Instruction>,
+    max_instructions: usize,
+) -> Vec<Vec<Instruction>> {
+    let mut packed: Vec<Vec<Instruction>> = Vec::new();
+    let mut current: Vec<Instruction> = Vec::new();
+
+    for instruction in instructions {
+        current.push(instruction);
+
+        if current.len() > 1
+            && (current.len() > max_instructions
+                || transaction_size(payer, &current) > PACKET_DATA_SIZE)
+        {
+            let instruction = current.pop().unwrap();
+            packed.push(current);
+            current = vec![instruction];
+        }
+    }
+
+    if !current.is_empty() {
+        packed.push(current);
+    }
+
+    packed
+}
+
+fn transaction_size(payer: &Pubkey, instructions: &[Instruction]) -> usize {
+    let message = Message::new(instructions, Some(payer));
+    let signatures = message.header.num_required_signatures as usize;
+
+    // compact length prefix of the signatures followed by the signatures and the message
+    1 + signatures * 64 + message.serialize().len()
+}
+
+/// Sends the orders packed into as few transactions as possible. The result of
+/// every order is the signature of the transaction it was sent in, or its error.
+#[allow(clippy::too_many_arguments)]
+pub fn place_order_batch(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    payer: &Keypair,
+    coin_wallet: &Pubkey,
+    pc_wallet: &Pubkey,
+    state: &MarketPubkeys,
+    orders: &Pubkey,
+    new_orders: Vec<NewOrderInstructionV3>,
+) -> Vec<(u64, Result<Signature, String>)> {
+    let client_order_ids: Vec<u64> = new_orders
+        .iter()
+        .map(|new_order| new_order.client_order_id)
+        .collect();
+
+    let instructions: Vec<Instruction> = new_orders
+        .into_iter()
+        .map(|new_order| {
+            let wallet = match new_order.side {
+                Side::Ask => coin_wallet,
+                Side::Bid => pc_wallet,
+            };
+            new_order_instruction(
+                program_id,
+                &payer.pubkey(),
+                wallet,
+                state,
+                orders,
+                new_order,
+            )
+        })
+        .collect();
+
+    let mut results = Vec::new();
+    let mut client_order_ids = client_order_ids.into_iter();
+
+    let packed = pack_instructions(&payer.pubkey(), instructions, MAX_ORDERS_PER_TRANSACTION);
+
+    for instructions in packed {
+        let result = client
+            .get_latest_blockhash()
+            .and_then(|recent_hash| {
+                let txn = Transaction::new_signed_with_payer(
+                    &instructions,
+                    Some(&payer.pubkey()),
+                    &[payer],
+                    recent_hash,
+                );
+                client.send_and_confirm_transaction(&txn)
+            })
+            .map_err(|err| err.to_string());
+
+        for client_order_id in client_order_ids.by_ref().take(instructions.len()) {
+            results.push((client_order_id, result.clone()));
+        }
+    }
+
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_batch_csv_ok() {
+        let content = "side,size,rate,type,client_id\n\
+                       lend,100,5,post-only,42\n\
+                       \n\
+                       borrow,10,7,,\n";
+
+        let orders = parse_batch_csv(content).unwrap();
+        assert_eq!(orders.len(), 2);
+        assert_eq!(
+            orders[0],
+            BatchOrder {
+                side: OrderSide::Lend,
+                size: 100,
+                rate: 5,
+                order_type: OrderKind::PostOnly,
+                client_id: Some(42),
+            }
+        );
+        assert_eq!(orders[1].side, OrderSide::Borrow);
+        assert_eq!(orders[1].order_type, OrderKind::Limit);
+        assert_eq!(orders[1].client_id, None);
+    }
+
+    #[test]
+    fn test_parse_batch_csv_failed() {
+        let result = parse_batch_csv("side,size\nlend,100\n");
+        assert_eq!(result.is_err(), true);
+
+        let result = parse_batch_csv("side,size,rate\nsell,100,5\n");
+        assert_eq!(result.is_err(), true);
+
+        let result = parse_batch_csv("side,size,rate\nlend,many,5\n");
+        assert_eq!(result.is_err(), true);
+    }
+
+    #[test]
+    fn test_parse_batch_json_ok() {
+        let content = r#"[
+            {"side": "lend", "size": 100, "rate": 5},
+            {"side": "borrow", "size": 10, "rate": 7, "type": "ioc", "client_id": 3}
+        ]"#;
+
+        let orders = parse_batch_json(content).unwrap();
+        assert_eq!(orders.len(), 2);
+        assert_eq!(orders[0].order_type, OrderKind::Limit);
+        assert_eq!(orders[1].order_type, OrderKind::Ioc);
+        assert_eq!(orders[1].client_id, Some(3));
+    }
+
+    #[test]
+    fn test_pack_instructions_ok() {
+        let payer = Pubkey::new_unique();
+        let program_id = Pubkey::new_unique();
+        let instruction = Instruction::new_with_bytes(program_id, &[0; 300], vec![]);
+
+        let packed = pack_instructions(&payer, vec![instruction.clone(); 5], 10);
+        assert_eq!(packed.len(), 2);
+        assert_eq!(packed[0].len(), 3);
+        assert_eq!(packed[1].len(), 2);
+
+        let instruction = Instruction::new_with_bytes(program_id, &[0; 10], vec![]);
+        let packed = pack_instructions(&payer, vec![instruction; 7], 3);
+        assert_eq!(packed.len(), 3);
+        assert_eq!(packed[0].len(), 3);
+        assert_eq!(packed[2].len(), 1);
+    }
+
+    #[test]
+    fn test_parse_batch_duplicate_client_id_failed() {
+        let content = "side,size,rate,type,client_id\n\
+                       lend,100,5,,42\n\
+                       borrow,10,7,,42\n";
+        assert_eq!(parse_batch_csv(content).is_err(), true);
+
+        let content = r#"[
+            {"side": "lend", "size": 100, "rate": 5, "client_id": 7},
+            {"side": "borrow", "size": 10, "rate": 7},
+            {"side": "lend", "size": 20, "rate": 6, "client_id": 7}
+        ]"#;
+        assert_eq!(parse_batch_json(content).is_err(), true);
+
+        let content = "side,size,rate,client_id\nlend,100,5,1\nlend,100,5,\nlend,100,5,\n";
+        assert_eq!(parse_batch_csv(content).unwrap().len(), 3);
+    }
+}
@@ -1,15 +1,12 @@
-use std::{num::NonZeroU64, str::FromStr, time::SystemTime};
+use std::{collections::BTreeMap, str::FromStr, time::Duration};
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use debug_print::debug_println;
 
-use serum_dex::{
-    instruction::{NewOrderInstructionV3, SelfTradeBehavior},
-    matching::{OrderType, Side},
-};
+use serum_dex::instruction::SelfTradeBehavior;
 
 use solana_client::rpc_client::RpcClient;
-use solana_sdk::{pubkey::Pubkey, signer::Signer};
+use solana_sdk::{pubkey::Pubkey, signature::Signature, signer::Signer};
 
 use serum_rust_cli::*;
 
@@ -18,6 +15,14 @@ use serum_rust_cli::*;
 #[clap(propagate_version = true)]
 /// A simple CLI application to interact with Serum DEX to place new order, fetch orders, match orders and settle funds.
 struct Arguments {
+    #[clap(long, value_enum, global = true, default_value = "table")]
+    /// Output format of the command results
+    output: OutputArg,
+
+    #[clap(long, global = true)]
+    /// Refetch the cached market keys, mints, lot sizes and decimals before running a command which uses the market
+    refresh: bool,
+
     #[clap(subcommand)]
     command: Commands,
 }
@@ -46,6 +51,24 @@ enum Commands {
         /// pc mint pubkey
         pc_mint: String,
     },
+    /// Attach to an existing Serum market instead of creating a new one.
+    Attach {
+        #[clap(long, forbid_empty_values = true, validator = validate_url_address)]
+        /// Network URL, for instance: http://localhost:8899
+        url: String,
+
+        #[clap(long, forbid_empty_values = true, validator = validate_input_for_space)]
+        /// Path to your wallet, such as ~/.config/solana/id.json, the wallet should have some funds
+        path: String,
+
+        #[clap(long, forbid_empty_values = true, validator = validate_input_for_space)]
+        /// Program ID of the Serum DEX
+        program_id: String,
+
+        #[clap(long, forbid_empty_values = true, validator = validate_input_for_space)]
+        /// market pubkey
+        market: String,
+    },
     /// Get info about mint, wallet, network, program, market and open order
     Info {},
     /// Place new order to lend
@@ -58,13 +81,8 @@ enum Commands {
         /// coin mint pubkey
         coin_mint: String,
 
-        #[clap(long, forbid_empty_values = true)]
-        /// The size of the order.
-        size: u64,
-
-        #[clap(long = "rate", forbid_empty_values = true)]
-        /// The interest rate of the order.
-        interest_rate: u64,
+        #[clap(flatten)]
+        order: OrderArgs,
     },
     /// Place new order to borrow
     Borrow {
@@ -76,22 +94,432 @@ enum Commands {
         /// pc mint pubkey
         pc_mint: String,
 
+        #[clap(flatten)]
+        order: OrderArgs,
+    },
+    /// Replace an existing order with a new rate and size in a single transaction
+    Amend {
+        #[clap(long, forbid_empty_values = true, validator = validate_input_for_space)]
+        /// Path to your wallet, such as ~/.config/solana/id.json, the wallet should have some funds
+        wallet: String,
+
+        #[clap(long, forbid_empty_values = true, validator = validate_input_for_space)]
+        /// coin mint pubkey for lending orders, pc mint pubkey for borrowing orders
+        mint: String,
+
         #[clap(long, forbid_empty_values = true)]
-        /// The size of the order.
-        size: u64,
+        /// Client order id of the order to replace
+        replace: u64,
+
+        #[clap(flatten)]
+        order: OrderArgs,
+    },
+    /// Place orders listed in a CSV or JSON file, packing up to 5 orders per transaction
+    PlaceBatch {
+        #[clap(long, forbid_empty_values = true, validator = validate_input_for_space)]
+        /// Path to your wallet, such as ~/.config/solana/id.json, the wallet should have some funds
+        wallet: String,
+
+        #[clap(long, forbid_empty_values = true, validator = validate_input_for_space)]
+        /// coin mint pubkey
+        coin_mint: String,
+
+        #[clap(long, forbid_empty_values = true, validator = validate_input_for_space)]
+        /// pc mint pubkey
+        pc_mint: String,
+
+        #[clap(long, forbid_empty_values = true, validator = validate_input_for_space)]
+        /// Path to a CSV file with side,size,rate,type,client_id columns or a JSON array of orders
+        file: String,
+    },
+    /// Cancel orders whose good-till-time has passed
+    SweepExpired {
+        #[clap(long, forbid_empty_values = true, validator = validate_input_for_space)]
+        /// Path to your wallet, such as ~/.config/solana/id.json, the wallet should have some funds
+        wallet: String,
+    },
+    /// Show balances and orders of the wallet's open orders account
+    OpenOrders {
+        #[clap(long, forbid_empty_values = true, validator = validate_input_for_space)]
+        /// Path to your wallet, such as ~/.config/solana/id.json
+        wallet: String,
+    },
+    /// Find the wallet's open orders accounts on-chain and record one of them in the config
+    RecoverOpenOrders {
+        #[clap(long, forbid_empty_values = true, validator = validate_input_for_space)]
+        /// Path to your wallet, such as ~/.config/solana/id.json
+        wallet: String,
+
+        #[clap(long, validator = validate_input_for_space)]
+        /// Open orders account to record, the first one found is used when none is recorded yet
+        address: Option<String>,
+    },
+    /// Close the wallet's open orders account and reclaim its rent
+    CloseOpenOrders {
+        #[clap(long, forbid_empty_values = true, validator = validate_input_for_space)]
+        /// Path to your wallet, such as ~/.config/solana/id.json
+        wallet: String,
+
+        #[clap(long, validator = validate_input_for_space)]
+        /// Account receiving the rent lamports, the wallet by default
+        destination: Option<String>,
 
-        #[clap(long = "rate", forbid_empty_values = true)]
-        /// The interest rate of the order.
-        interest_rate: u64,
+        #[clap(long)]
+        /// Settle free funds to the wallet's token accounts before closing
+        settle: bool,
+
+        #[clap(long, validator = validate_input_for_space)]
+        /// coin mint pubkey, required to settle funds
+        coin_mint: Option<String>,
+
+        #[clap(long, validator = validate_input_for_space)]
+        /// pc mint pubkey, required to settle funds
+        pc_mint: Option<String>,
     },
     /// Displays orders from OrderBook
-    Fetch {},
-    /// Remove config files
-    Clean {},
+    Fetch {
+        #[clap(long)]
+        /// Keep redrawing the orderbook whenever it changes
+        watch: bool,
+
+        #[clap(long)]
+        /// Only show this many of the best orders of each side
+        limit: Option<usize>,
+
+        #[clap(long, default_value = "2")]
+        /// Seconds between updates when websockets aren't available in watch mode
+        interval: u64,
+
+        #[clap(long, forbid_empty_values = true, validator = validate_input_for_space)]
+        /// Websocket URL for watch mode, derived from the network URL by default
+        ws_url: Option<String>,
+    },
+    /// Display the fills and cancellations left in the event queue
+    Events {
+        #[clap(long, forbid_empty_values = true, validator = validate_input_for_space)]
+        /// Only show the events of the open orders account of this wallet
+        wallet: Option<String>,
+    },
+    /// Display the orders and cancellations waiting in the request queue to be matched
+    Requests {},
+    /// Record the wallet's fills found in the event queue and display its fill history
+    ///
+    /// Only fills still in the event queue are found, fills cranked out of it between two runs
+    /// are missed. Fills have no on-chain time, so each one is stamped with the time it was
+    /// first seen by this command.
+    Fills {
+        #[clap(long, forbid_empty_values = true, validator = validate_input_for_space)]
+        /// Path to your wallet, such as ~/.config/solana/id.json
+        wallet: String,
+
+        #[clap(long, forbid_empty_values = true, validator = validate_input_for_space)]
+        /// Export the fill history to this CSV file
+        csv: Option<String>,
+    },
+    /// Decode the Serum instructions, token balance changes and logs of a transaction
+    Tx {
+        #[clap(forbid_empty_values = true, validator = validate_input_for_space)]
+        /// Signature of the transaction
+        signature: String,
+    },
+    /// Full-screen terminal UI with the book, open orders, fills, balances and an order form
+    Tui {
+        #[clap(long, forbid_empty_values = true, validator = validate_input_for_space)]
+        /// Path to your wallet, such as ~/.config/solana/id.json, the wallet should have some funds
+        wallet: String,
+
+        #[clap(long, forbid_empty_values = true, validator = validate_input_for_space)]
+        /// coin mint pubkey
+        coin_mint: String,
+
+        #[clap(long, forbid_empty_values = true, validator = validate_input_for_space)]
+        /// pc mint pubkey
+        pc_mint: String,
+
+        #[clap(long, default_value = "5")]
+        /// Seconds between updates of the screen
+        interval: u64,
+    },
+    /// Interactive shell placing and cancelling orders over a single connection
+    Shell {
+        #[clap(long, forbid_empty_values = true, validator = validate_input_for_space)]
+        /// Path to your wallet, such as ~/.config/solana/id.json, the wallet should have some funds
+        wallet: String,
+
+        #[clap(long, forbid_empty_values = true, validator = validate_input_for_space)]
+        /// coin mint pubkey
+        coin_mint: String,
+
+        #[clap(long, forbid_empty_values = true, validator = validate_input_for_space)]
+        /// pc mint pubkey
+        pc_mint: String,
+    },
+    /// Remove config files, a backup is written first
+    Clean {
+        #[clap(long)]
+        /// Remove config files even if the open orders account still has orders or funds
+        force: bool,
+
+        #[clap(long)]
+        /// Only show what would be removed
+        dry_run: bool,
+    },
+    /// Restore config files from a backup written by clean
+    Restore {
+        #[clap(long, validator = validate_input_for_space)]
+        /// Path to the backup, the latest one is used by default
+        archive: Option<String>,
+    },
+}
+
+impl Commands {
+    /// Whether the command reads the market cache. Init and attach write a new cache,
+    /// clean and restore only touch the config files.
+    fn uses_market_cache(&self) -> bool {
+        !matches!(
+            self,
+            Commands::Init { .. }
+                | Commands::Attach { .. }
+                | Commands::Clean { .. }
+                | Commands::Restore { .. }
+        )
+    }
+}
+
+#[derive(Args, Debug)]
+struct OrderArgs {
+    #[clap(long, forbid_empty_values = true)]
+    /// The size of the order.
+    size: u64,
+
+    #[clap(long = "rate", forbid_empty_values = true)]
+    /// The interest rate of the order.
+    interest_rate: u64,
+
+    #[clap(long = "type", value_enum, default_value = "limit")]
+    /// The type of the order.
+    order_type: OrderKind,
+
+    #[clap(long = "self-trade", value_enum, default_value = "decrement-take")]
+    /// What happens when the order would match against an order of the same wallet.
+    self_trade: SelfTradeArg,
+
+    #[clap(long)]
+    /// Client order id of the order, a unique one is generated when omitted.
+    client_id: Option<u64>,
+
+    #[clap(long, conflicts_with = "expires-at")]
+    /// Number of seconds the transaction has to land on-chain before the order is rejected, 20 by default.
+    expires_in: Option<u64>,
+
+    #[clap(long)]
+    /// Unix timestamp until which the transaction has to land on-chain.
+    expires_at: Option<i64>,
+
+    #[clap(long, conflicts_with = "good-till")]
+    /// Keep the order in the book for the given number of seconds, sweep-expired cancels it afterwards.
+    good_for: Option<u64>,
+
+    #[clap(long)]
+    /// Keep the order in the book until the given unix timestamp, sweep-expired cancels it afterwards.
+    good_till: Option<i64>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputArg {
+    Table,
+    Json,
+    JsonCompact,
+}
+
+impl From<OutputArg> for OutputFormat {
+    fn from(arg: OutputArg) -> Self {
+        match arg {
+            OutputArg::Table => OutputFormat::Table,
+            OutputArg::Json => OutputFormat::Json,
+            OutputArg::JsonCompact => OutputFormat::JsonCompact,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum SelfTradeArg {
+    DecrementTake,
+    CancelProvide,
+    AbortTransaction,
+}
+
+impl From<SelfTradeArg> for SelfTradeBehavior {
+    fn from(arg: SelfTradeArg) -> Self {
+        match arg {
+            SelfTradeArg::DecrementTake => SelfTradeBehavior::DecrementTake,
+            SelfTradeArg::CancelProvide => SelfTradeBehavior::CancelProvide,
+            SelfTradeArg::AbortTransaction => SelfTradeBehavior::AbortTransaction,
+        }
+    }
+}
+
+/// Connects to the network from the config and reads the program id and market pubkey.
+fn connect() -> (RpcClient, Pubkey, Pubkey) {
+    let path = CONFIG_DIR.to_string() + "/" + URL;
+    let url = read_file(path.as_str()).unwrap();
+    let client = RpcClient::new(&url);
+
+    let path = CONFIG_DIR.to_string() + "/" + PROGRAM_ID;
+    let program_id = read_file(path.as_str()).unwrap();
+    let program_id_pk = Pubkey::from_str(&program_id).unwrap();
+
+    let path = CONFIG_DIR.to_string() + "/" + MARKET_PUBKEY;
+    let market_str = read_file(path.as_str()).unwrap();
+    let market_pk = Pubkey::from_str(market_str.as_str()).unwrap();
+
+    (client, program_id_pk, market_pk)
+}
+
+/// Prints the error in the requested format and exits with a failure status.
+fn exit_with_error(format: OutputFormat, error: impl ToString) -> ! {
+    render_error(format, error);
+    std::process::exit(1);
+}
+
+/// Resolves a deadline given either relative to now or as an absolute unix timestamp,
+/// clap makes sure only one of them is set.
+fn resolve_deadline(relative: Option<u64>, absolute: Option<i64>) -> Result<Option<i64>, String> {
+    match relative {
+        Some(seconds) => {
+            let now = unix_timestamp().map_err(|err| err.to_string())?;
+            i64::try_from(seconds)
+                .ok()
+                .and_then(|seconds| now.checked_add(seconds))
+                .map(Some)
+                .ok_or_else(|| format!("{} seconds from now is out of range", seconds))
+        }
+        None => Ok(absolute),
+    }
+}
+
+/// Places a new order, or replaces the order with the client order id given in `replace`.
+fn place_new_order(
+    side: OrderSide,
+    wallet: &str,
+    mint: &str,
+    args: OrderArgs,
+    replace: Option<u64>,
+    format: OutputFormat,
+) {
+    let (client, program_id_pk, market_pk) = connect();
+
+    let payer = read_keypair_file(wallet).unwrap();
+
+    let max_ts = match resolve_deadline(args.expires_in, args.expires_at) {
+        Ok(max_ts) => max_ts,
+        Err(err) => {
+            exit_with_error(format, format!("Invalid order expiry: {}", err));
+        }
+    };
+
+    let expires_at = match resolve_deadline(args.good_for, args.good_till) {
+        Ok(expires_at) => expires_at,
+        Err(err) => {
+            exit_with_error(format, format!("Invalid good-till-time: {}", err));
+        }
+    };
+
+    let mut session = match TradingSession::new(&client, &program_id_pk, &market_pk, &payer) {
+        Ok(session) => session,
+        Err(err) => {
+            exit_with_error(format, format!("Unable to load the market: {}", err));
+        }
+    };
+
+    let mint = Pubkey::from_str(mint).unwrap();
+    if let Err(err) = session.check_mint(side, &mint) {
+        exit_with_error(format, format!("Invalid mint: {}", err));
+    }
+
+    let request = OrderRequest {
+        order_type: args.order_type,
+        self_trade_behavior: args.self_trade.into(),
+        client_order_id: args.client_id,
+        max_ts,
+        expires_at,
+        replace,
+        ..OrderRequest::new(side, args.size, args.interest_rate)
+    };
+
+    match session.place(request) {
+        Ok(output) => {
+            if let Err(err) = render(format, &output) {
+                debug_println!("{:?}", err);
+            }
+        }
+        Err(err) => exit_with_error(format, format!("{:?}", err)),
+    }
+}
+
+/// Checks the open orders accounts recorded in the config files on-chain and
+/// describes the ones which still have orders or unsettled funds.
+fn check_open_orders_before_clean(files: &[String]) -> Vec<String> {
+    let open_order_files: Vec<&String> = files
+        .iter()
+        .filter(|file_name| file_name.ends_with(OPEN_ORDER))
+        .collect();
+
+    if open_order_files.is_empty() {
+        return Vec::new();
+    }
+
+    let path = CONFIG_DIR.to_string() + "/" + URL;
+    let url = match read_file(path.as_str()) {
+        Ok(url) => url,
+        Err(err) => return vec![format!("unable to check open orders accounts: {}", err)],
+    };
+    let client = RpcClient::new(&url);
+
+    let mut problems = Vec::new();
+    for file_name in open_order_files {
+        let path = CONFIG_DIR.to_string() + "/" + file_name;
+        let orders = match read_file(path.as_str()).map(|content| Pubkey::from_str(&content)) {
+            Ok(Ok(orders)) => orders,
+            _ => {
+                problems.push(format!("{} doesn't hold a valid pubkey", file_name));
+                continue;
+            }
+        };
+
+        match load_open_orders(&client, &orders) {
+            Ok(open_orders) => {
+                if let Err(err) = check_open_orders_closable(&open_orders, false) {
+                    problems.push(format!("{}: {}", orders, err));
+                }
+            }
+            Err(err) => problems.push(format!("unable to load {}: {}", orders, err)),
+        }
+    }
+
+    problems
 }
 
 fn main() {
     let args = Arguments::parse();
+    let format = OutputFormat::from(args.output);
+
+    if args.refresh && args.command.uses_market_cache() && std::path::Path::new(CONFIG_DIR).exists()
+    {
+        let (client, program_id_pk, market_pk) = connect();
+        match refresh_market_cache(&client, &program_id_pk, &market_pk) {
+            Ok((_, stale)) if !stale.is_empty() => {
+                eprintln!("Updated stale market cache fields: {}", stale.join(", "));
+            }
+            Ok(_) => {}
+            Err(err) => {
+                exit_with_error(
+                    format,
+                    format!("Unable to refresh the market cache: {}", err),
+                );
+            }
+        }
+    }
 
     match args.command {
         Commands::Init {
@@ -102,8 +530,7 @@ fn main() {
             pc_mint,
         } => {
             if std::path::Path::new(CONFIG_DIR).exists() {
-                println!("To initialize and generate new on-chain accounts and market, please, firstly run clean command.");
-                return;
+                exit_with_error(format, "To initialize and generate new on-chain accounts and market, please, firstly run clean command.");
             }
 
             let client = RpcClient::new(&url);
@@ -123,20 +550,40 @@ fn main() {
                 &program_id_pk,
             );
 
-            if let Ok(market_keys) = market_keys_result {
-                if let Err(err) = write_file(
-                    CONFIG_DIR,
-                    MARKET_PUBKEY,
-                    market_keys.market.to_string().as_str(),
-                ) {
-                    debug_println!("{:?}", err);
+            let (market_keys, signatures) = match market_keys_result {
+                Ok(result) => result,
+                Err(err) => {
+                    exit_with_error(format, format!("Unable to initialize market: {:?}", err));
+                }
+            };
+
+            // the lot sizes and fees are read back from the created market
+            let market_info = match load_market_info(&client, &program_id_pk, &market_keys.market) {
+                Ok(market_info) => market_info,
+                Err(err) => {
+                    exit_with_error(
+                        format,
+                        format!(
+                            "Market {} is created, but it couldn't be read back: {:?}. \
+                            Run attach with it to finish the setup.",
+                            market_keys.market, err
+                        ),
+                    );
                 }
+            };
 
-                debug_println!("Market keys: {:#?}", market_keys);
-            } else {
-                debug_println!("{:?}", market_keys_result.err());
+            if let Err(err) = save_market_info(&market_keys.market, &market_info) {
+                debug_println!("{:?}", err);
             }
 
+            let market_cache =
+                MarketCache::from_info(&client, &program_id_pk, &market_keys, &market_info);
+            if let Err(err) = market_cache.and_then(|cache| cache.save(CONFIG_DIR)) {
+                debug_println!("{:?}", err);
+            }
+
+            debug_println!("Market keys: {:#?}", market_keys);
+
             // saving data into json files
             if let Err(err) = write_file(CONFIG_DIR, URL, &url) {
                 debug_println!("{:?}", err);
@@ -149,151 +596,430 @@ fn main() {
             if let Err(err) = write_file(CONFIG_DIR, WALLET, &path) {
                 debug_println!("{:?}", err);
             }
+
+            let output = InitOutput {
+                market: MarketKeysOutput::from(&market_keys),
+                coin_mint: coin.to_string(),
+                pc_mint: pc.to_string(),
+                url,
+                program_id,
+                wallet: path,
+                signatures: signatures
+                    .iter()
+                    .map(|signature| signature.to_string())
+                    .collect(),
+            };
+
+            if let Err(err) = render(format, &output) {
+                debug_println!("{:?}", err);
+            }
+        }
+        Commands::Attach {
+            url,
+            path,
+            program_id,
+            market,
+        } => {
+            if std::path::Path::new(CONFIG_DIR).exists() {
+                exit_with_error(
+                    format,
+                    "To attach to another market, please, firstly run clean command.",
+                );
+            }
+
+            let client = RpcClient::new(&url);
+            let program_id_pk = Pubkey::from_str(&program_id).unwrap();
+            let market_pk = Pubkey::from_str(&market).unwrap();
+
+            let (market_keys, market_info) = match load_market(&client, &program_id_pk, &market_pk)
+            {
+                Ok(market) => market,
+                Err(err) => {
+                    exit_with_error(
+                        format,
+                        format!("Unable to attach to market {}: {}", market_pk, err),
+                    );
+                }
+            };
+
+            if let Err(err) = save_market_info(&market_pk, &market_info) {
+                debug_println!("{:?}", err);
+            }
+
+            let market_cache =
+                MarketCache::from_info(&client, &program_id_pk, &market_keys, &market_info);
+            if let Err(err) = market_cache.and_then(|cache| cache.save(CONFIG_DIR)) {
+                debug_println!("{:?}", err);
+            }
+
+            if let Err(err) = write_file(CONFIG_DIR, URL, &url) {
+                debug_println!("{:?}", err);
+            }
+
+            if let Err(err) = write_file(CONFIG_DIR, PROGRAM_ID, &program_id) {
+                debug_println!("{:?}", err);
+            }
+
+            if let Err(err) = write_file(CONFIG_DIR, WALLET, &path) {
+                debug_println!("{:?}", err);
+            }
+
+            let output = AttachOutput {
+                market: MarketKeysOutput::from(&market_keys),
+                coin_mint: market_info.coin_mint.to_string(),
+                pc_mint: market_info.pc_mint.to_string(),
+                coin_lot_size: market_info.coin_lot_size,
+                pc_lot_size: market_info.pc_lot_size,
+                fee_rate_bps: market_info.fee_rate_bps,
+                url,
+                program_id,
+                wallet: path,
+            };
+
+            if let Err(err) = render(format, &output) {
+                debug_println!("{:?}", err);
+            }
         }
         Commands::Info {} => {
-            info();
+            info(format);
         }
         Commands::Lend {
             wallet,
             coin_mint,
-            size,
-            interest_rate,
+            order,
         } => {
-            let path = CONFIG_DIR.to_string() + "/" + URL;
-            let url = read_file(path.as_str()).unwrap();
-            let client = RpcClient::new(&url);
+            place_new_order(OrderSide::Lend, &wallet, &coin_mint, order, None, format);
+        }
+        Commands::Borrow {
+            wallet,
+            pc_mint,
+            order,
+        } => {
+            place_new_order(OrderSide::Borrow, &wallet, &pc_mint, order, None, format);
+        }
+        Commands::Amend {
+            wallet,
+            mint,
+            replace,
+            order,
+        } => {
+            let owner = read_keypair_file(&wallet).unwrap();
+            let journal = OrderJournal::load(CONFIG_DIR, &owner.pubkey()).unwrap();
 
-            let path = CONFIG_DIR.to_string() + "/" + PROGRAM_ID;
-            let program_id = read_file(path.as_str()).unwrap();
-            let program_id_pk = Pubkey::from_str(&program_id).unwrap();
+            match journal.find(replace) {
+                Some(entry) => {
+                    place_new_order(entry.side, &wallet, &mint, order, Some(replace), format);
+                }
+                None => exit_with_error(
+                    format,
+                    format!(
+                        "There is no order with client order id {} in the journal",
+                        replace
+                    ),
+                ),
+            }
+        }
+        Commands::PlaceBatch {
+            wallet,
+            coin_mint,
+            pc_mint,
+            file,
+        } => {
+            let batch = match read_batch_file(&file) {
+                Ok(batch) => batch,
+                Err(err) => {
+                    exit_with_error(format, format!("Invalid batch file: {}", err));
+                }
+            };
 
+            let (client, program_id_pk, market_pk) = connect();
             let payer = read_keypair_file(&wallet).unwrap();
 
-            let path = CONFIG_DIR.to_string() + "/" + MARKET_PUBKEY;
-            let market_str = read_file(path.as_str()).unwrap();
-            let market_pk = &Pubkey::from_str(market_str.as_str()).unwrap();
-            let market_keys = get_keys_for_market(&client, &program_id_pk, &market_pk).unwrap();
+            let mut session = match TradingSession::new(&client, &program_id_pk, &market_pk, &payer)
+            {
+                Ok(session) => session,
+                Err(err) => {
+                    exit_with_error(format, format!("Unable to load the market: {}", err));
+                }
+            };
 
             let coin_mint = Pubkey::from_str(coin_mint.as_str()).unwrap();
-            let associated_token = spl_associated_token_account::get_associated_token_address(
-                &payer.pubkey(),
-                &coin_mint,
-            );
+            let pc_mint = Pubkey::from_str(pc_mint.as_str()).unwrap();
+            if let Err(err) = session.check_mints(&coin_mint, &pc_mint) {
+                exit_with_error(format, format!("Invalid mint: {}", err));
+            }
 
-            let open_order_result =
-                get_open_order_pubkey(&client, &program_id_pk, &payer, &market_keys);
+            match session.place_batch(&batch) {
+                Ok(output) => {
+                    if let Err(err) = render(format, &output) {
+                        debug_println!("{:?}", err);
+                    }
+                }
+                Err(err) => exit_with_error(format, format!("{:?}", err)),
+            }
+        }
+        Commands::SweepExpired { wallet } => {
+            let (client, program_id_pk, market_pk) = connect();
+            let owner = read_keypair_file(&wallet).unwrap();
 
-            let mut orders = if open_order_result.is_ok() {
-                open_order_result.ok()
-            } else {
-                None
+            let orders = match read_open_order_pubkey(&owner.pubkey()) {
+                Ok(orders) => orders,
+                Err(_) => {
+                    exit_with_error(format, "There is no open orders account for this wallet");
+                }
             };
-            debug_println!("Open orders: {:?}", orders);
 
-            let now = SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_secs() as i64;
+            let mut journal = OrderJournal::load(CONFIG_DIR, &owner.pubkey()).unwrap();
+            let expired = journal.expired_client_order_ids(unix_timestamp().unwrap());
 
-            debug_println!("Placing new order...");
-            let result = place_order(
-                &client,
-                &program_id_pk,
-                &payer,
-                &associated_token,
-                &market_keys,
-                &mut orders,
-                NewOrderInstructionV3 {
-                    side: Side::Ask,
-                    limit_price: NonZeroU64::new(interest_rate).unwrap(),
-                    max_coin_qty: NonZeroU64::new(size).unwrap(),
-                    max_native_pc_qty_including_fees: NonZeroU64::new(std::u64::MAX).unwrap(),
-                    order_type: OrderType::Limit,
-                    limit: std::u16::MAX,
-                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
-                    client_order_id: 1_000_000,
-                    max_ts: now + 20,
-                },
-            );
+            let mut output = SweepOutput {
+                cancelled: Vec::new(),
+                gone: Vec::new(),
+                error: None,
+            };
 
-            if result.is_err() {
-                println!("{:?}", result.err());
-            } else {
-                println!("New order is placed in Orderbook");
+            if expired.is_empty() {
+                if let Err(err) = render(format, &output) {
+                    debug_println!("{:?}", err);
+                }
+                return;
+            }
+
+            let market_keys = get_cached_keys_for_market(&client, &program_id_pk, &market_pk);
+            let market_keys = match market_keys {
+                Ok(market_keys) => market_keys,
+                Err(err) => exit_with_error(format, format!("Unable to load the market: {}", err)),
+            };
+            let open_orders = load_open_orders(&client, &orders).unwrap();
+            let resting = resting_client_order_ids(&open_orders);
+
+            let to_cancel: Vec<u64> = expired
+                .iter()
+                .copied()
+                .filter(|client_order_id| resting.contains(client_order_id))
+                .collect();
+
+            for chunk in to_cancel.chunks(8) {
+                let mut client_order_ids = [0u64; 8];
+                client_order_ids[..chunk.len()].copy_from_slice(chunk);
+
+                let result = cancel_order_by_client_order_ids(
+                    &client,
+                    &owner,
+                    &program_id_pk,
+                    &market_keys,
+                    &orders,
+                    client_order_ids,
+                );
+
+                match result {
+                    Ok(signature) => {
+                        journal.mark_cancelled(chunk, Some(&signature.to_string()));
+                        output.cancelled.push(CancelOutput {
+                            client_order_ids: chunk.to_vec(),
+                            signature: signature.to_string(),
+                            explorer_link: explorer_link(&signature.to_string(), &client.url()),
+                        });
+                    }
+                    Err(err) => {
+                        output.error = Some(format!(
+                            "Failed to cancel expired orders {:?}: {:?}",
+                            chunk, err
+                        ));
+                        break;
+                    }
+                }
+            }
+
+            if output.error.is_none() {
+                output.gone = expired
+                    .iter()
+                    .copied()
+                    .filter(|client_order_id| !to_cancel.contains(client_order_id))
+                    .collect();
+                journal.mark_cancelled(&output.gone, None);
+            }
+
+            // orders cancelled before a failure are recorded as well
+            if let Err(err) = journal.save(CONFIG_DIR, &owner.pubkey()) {
+                debug_println!("{:?}", err);
+            }
+
+            if let Err(err) = render(format, &output) {
+                debug_println!("{:?}", err);
+            }
+
+            // the orders cancelled before the failure are reported, but the sweep failed
+            if output.error.is_some() {
+                std::process::exit(1);
             }
         }
-        Commands::Borrow {
-            wallet,
-            pc_mint,
-            size,
-            interest_rate,
-        } => {
-            let path = CONFIG_DIR.to_string() + "/" + URL;
-            let url = read_file(path.as_str()).unwrap();
-            let client = RpcClient::new(&url);
+        Commands::OpenOrders { wallet } => {
+            let (client, program_id_pk, market_pk) = connect();
+            let owner = read_keypair_file(&wallet).unwrap();
 
-            let path = CONFIG_DIR.to_string() + "/" + PROGRAM_ID;
-            let program_id = read_file(path.as_str()).unwrap();
-            let program_id_pk = Pubkey::from_str(&program_id).unwrap();
+            let orders = match read_open_order_pubkey(&owner.pubkey()) {
+                Ok(orders) => orders,
+                Err(_) => {
+                    exit_with_error(format, "There is no open orders account for this wallet");
+                }
+            };
 
-            let payer = read_keypair_file(&wallet).unwrap();
+            let summary =
+                match get_open_orders_summary(&client, &program_id_pk, &market_pk, &orders) {
+                    Ok(summary) => summary,
+                    Err(err) => {
+                        exit_with_error(format, format!("{:?}", err));
+                    }
+                };
 
-            let path = CONFIG_DIR.to_string() + "/" + MARKET_PUBKEY;
-            let market_str = read_file(path.as_str()).unwrap();
-            let market_pk = &Pubkey::from_str(market_str.as_str()).unwrap();
-            let market_keys = get_keys_for_market(&client, &program_id_pk, &market_pk).unwrap();
+            if let Err(err) = render(format, &OpenOrdersOutput::from(&summary)) {
+                debug_println!("{:?}", err);
+            }
+        }
+        Commands::RecoverOpenOrders { wallet, address } => {
+            let (client, program_id_pk, market_pk) = connect();
+            let owner = read_keypair_file(&wallet).unwrap();
 
-            let pc_mint = Pubkey::from_str(pc_mint.as_str()).unwrap();
-            let associated_token = spl_associated_token_account::get_associated_token_address(
-                &payer.pubkey(),
-                &pc_mint,
-            );
+            let found =
+                find_open_orders_accounts(&client, &program_id_pk, &market_pk, &owner.pubkey())
+                    .unwrap();
+
+            if found.is_empty() {
+                exit_with_error(
+                    format,
+                    "There are no open orders accounts of this wallet on-chain",
+                );
+            }
 
-            let open_order_result =
-                get_open_order_pubkey(&client, &program_id_pk, &payer, &market_keys);
+            let recorded = read_open_order_pubkey(&owner.pubkey()).ok();
 
-            let mut orders = if open_order_result.is_ok() {
-                open_order_result.ok()
-            } else {
-                None
+            let target = match address {
+                Some(address) => {
+                    let address = Pubkey::from_str(&address).unwrap();
+                    if !found.contains(&address) {
+                        exit_with_error(
+                            format,
+                            format!("{} isn't an open orders account of this wallet", address),
+                        );
+                    }
+                    Some(address)
+                }
+                None if recorded.is_none() => found.first().copied(),
+                None => None,
+            };
+
+            if let Some(target) = target {
+                if let Err(err) = adopt_open_orders(&owner.pubkey(), &target) {
+                    exit_with_error(format, format!("{:?}", err));
+                }
+            }
+
+            let output = RecoverOpenOrdersOutput {
+                accounts: found.iter().map(|orders| orders.to_string()).collect(),
+                recorded: recorded.map(|orders| orders.to_string()),
+                adopted: target.map(|orders| orders.to_string()),
+            };
+
+            if let Err(err) = render(format, &output) {
+                debug_println!("{:?}", err);
+            }
+        }
+        Commands::CloseOpenOrders {
+            wallet,
+            destination,
+            settle,
+            coin_mint,
+            pc_mint,
+        } => {
+            let (client, program_id_pk, market_pk) = connect();
+            let owner = read_keypair_file(&wallet).unwrap();
+
+            let orders = match read_open_order_pubkey(&owner.pubkey()) {
+                Ok(orders) => orders,
+                Err(_) => {
+                    exit_with_error(format, "There is no open orders account for this wallet");
+                }
+            };
+
+            let destination = match destination {
+                Some(destination) => Pubkey::from_str(&destination).unwrap(),
+                None => owner.pubkey(),
             };
 
-            let now = SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_secs() as i64;
+            let market_keys = get_cached_keys_for_market(&client, &program_id_pk, &market_pk);
+            let market_keys = match market_keys {
+                Ok(market_keys) => market_keys,
+                Err(err) => exit_with_error(format, format!("Unable to load the market: {}", err)),
+            };
 
-            debug_println!("Placing new order...");
-            let result = place_order(
+            let settle_wallets = match (settle, coin_mint, pc_mint) {
+                (false, _, _) => None,
+                (true, Some(coin_mint), Some(pc_mint)) => {
+                    let coin_mint = Pubkey::from_str(&coin_mint).unwrap();
+                    let pc_mint = Pubkey::from_str(&pc_mint).unwrap();
+                    Some((
+                        spl_associated_token_account::get_associated_token_address(
+                            &owner.pubkey(),
+                            &coin_mint,
+                        ),
+                        spl_associated_token_account::get_associated_token_address(
+                            &owner.pubkey(),
+                            &pc_mint,
+                        ),
+                    ))
+                }
+                (true, _, _) => {
+                    exit_with_error(
+                        format,
+                        "Both --coin-mint and --pc-mint are required to settle funds",
+                    );
+                }
+            };
+
+            let result = close_open_orders(
                 &client,
                 &program_id_pk,
-                &payer,
-                &associated_token,
+                &owner,
                 &market_keys,
-                &mut orders,
-                NewOrderInstructionV3 {
-                    side: Side::Bid,
-                    limit_price: NonZeroU64::new(interest_rate).unwrap(),
-                    max_coin_qty: NonZeroU64::new(size).unwrap(),
-                    max_native_pc_qty_including_fees: NonZeroU64::new(5_000_000).unwrap(),
-                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
-                    order_type: OrderType::Limit,
-                    client_order_id: 1_000_100,
-                    limit: std::u16::MAX,
-                    max_ts: now + 20,
-                },
+                &orders,
+                &destination,
+                settle_wallets
+                    .as_ref()
+                    .map(|(coin_wallet, pc_wallet)| (coin_wallet, pc_wallet)),
             );
 
-            if result.is_err() {
-                println!("{:?}", result.err());
-            } else {
-                println!("New order is placed in Orderbook");
+            match result {
+                Ok(signature) => {
+                    if let Err(err) = forget_open_orders(&owner.pubkey()) {
+                        debug_println!("{:?}", err);
+                    }
+
+                    let output = CloseOpenOrdersOutput {
+                        open_orders: orders.to_string(),
+                        destination: destination.to_string(),
+                        signature: signature.to_string(),
+                        explorer_link: explorer_link(&signature.to_string(), &client.url()),
+                    };
+
+                    if let Err(err) = render(format, &output) {
+                        debug_println!("{:?}", err);
+                    }
+                }
+                Err(err) => exit_with_error(
+                    format,
+                    format!("Unable to close open orders account: {}", err),
+                ),
             }
         }
-        Commands::Fetch {} => {
+        Commands::Fetch {
+            watch,
+            limit,
+            interval,
+            ws_url,
+        } => {
             if !std::path::Path::new(CONFIG_DIR).exists() {
-                println!("Missing config files!");
-                return;
+                exit_with_error(format, "Missing config files!");
             }
 
             let path = CONFIG_DIR.to_string() + "/" + URL;
@@ -309,14 +1035,293 @@ fn main() {
             let market_str = read_file(path.as_str()).unwrap();
             let market_pk = &Pubkey::from_str(market_str.as_str()).unwrap();
 
-            if let Err(err) = fetch_and_show_orders(&client, &program_id_pk, &market_pk) {
+            let result = if watch {
+                let ws_url = match ws_url.map(Ok).unwrap_or_else(|| websocket_url(&url)) {
+                    Ok(ws_url) => ws_url,
+                    Err(err) => exit_with_error(
+                        format,
+                        format!("Unable to derive the websocket URL, pass --ws-url: {}", err),
+                    ),
+                };
+                watch_and_show_orders(
+                    &client,
+                    &program_id_pk,
+                    market_pk,
+                    format,
+                    limit,
+                    &ws_url,
+                    Duration::from_secs(interval.max(1)),
+                )
+            } else {
+                fetch_and_show_orders(&client, &program_id_pk, market_pk, format, limit)
+            };
+
+            if let Err(err) = result {
+                exit_with_error(format, err);
+            }
+        }
+        Commands::Events { wallet } => {
+            let (client, program_id_pk, market_pk) = connect();
+            let market_keys = get_cached_keys_for_market(&client, &program_id_pk, &market_pk);
+            let market_keys = match market_keys {
+                Ok(market_keys) => market_keys,
+                Err(err) => exit_with_error(format, format!("Unable to load the market: {}", err)),
+            };
+
+            let orders = match wallet {
+                Some(wallet) => {
+                    let owner = read_keypair_file(&wallet).unwrap();
+                    match read_open_order_pubkey(&owner.pubkey()) {
+                        Ok(orders) => Some(orders),
+                        Err(_) => {
+                            exit_with_error(
+                                format,
+                                "There is no open orders account for this wallet",
+                            );
+                        }
+                    }
+                }
+                None => None,
+            };
+
+            match get_events(&client, &market_keys, orders.as_ref()) {
+                Ok(output) => {
+                    if let Err(err) = render(format, &output) {
+                        debug_println!("{:?}", err);
+                    }
+                }
+                Err(err) => exit_with_error(format, format!("Unable to load events: {}", err)),
+            }
+        }
+        Commands::Requests {} => {
+            let (client, program_id_pk, market_pk) = connect();
+            let market_keys = get_cached_keys_for_market(&client, &program_id_pk, &market_pk);
+            let market_keys = match market_keys {
+                Ok(market_keys) => market_keys,
+                Err(err) => exit_with_error(format, format!("Unable to load the market: {}", err)),
+            };
+
+            match get_requests(&client, &market_keys) {
+                Ok(output) => {
+                    if let Err(err) = render(format, &output) {
+                        debug_println!("{:?}", err);
+                    }
+                }
+                Err(err) => exit_with_error(format, format!("Unable to load requests: {}", err)),
+            }
+        }
+        Commands::Fills { wallet, csv } => {
+            let (client, program_id_pk, market_pk) = connect();
+            let owner = read_keypair_file(&wallet).unwrap();
+
+            let orders = match read_open_order_pubkey(&owner.pubkey()) {
+                Ok(orders) => orders,
+                Err(_) => {
+                    exit_with_error(format, "There is no open orders account for this wallet");
+                }
+            };
+
+            let market_cache = load_checked_market_cache(&client, &program_id_pk, &market_pk);
+            let market_cache = match market_cache {
+                Ok(market_cache) => market_cache,
+                Err(err) => exit_with_error(format, format!("Unable to load the market: {}", err)),
+            };
+            let market_keys = market_cache.market_keys().unwrap();
+
+            let events = match get_events(&client, &market_keys, None) {
+                Ok(events) => events,
+                Err(err) => {
+                    exit_with_error(format, format!("Unable to load events: {}", err));
+                }
+            };
+
+            let fills = fills_from_events(
+                &events.events,
+                &orders,
+                market_cache.coin_lot_size,
+                market_cache.pc_lot_size,
+                unix_timestamp().unwrap_or_default(),
+            );
+
+            let mut history = FillHistory::load(CONFIG_DIR, &owner.pubkey(), &market_pk).unwrap();
+            let new_fills = history.merge(fills);
+            if let Err(err) = history.save(CONFIG_DIR, &owner.pubkey(), &market_pk) {
+                debug_println!("{:?}", err);
+            }
+
+            if let Some(path) = &csv {
+                if let Err(err) = std::fs::write(path, history.to_csv()) {
+                    exit_with_error(
+                        format,
+                        format!("Unable to export fills to {}: {}", path, err),
+                    );
+                }
+            }
+
+            let output = FillsOutput {
+                open_orders: orders.to_string(),
+                new_fills,
+                csv,
+                fills: history.fills,
+            };
+
+            if let Err(err) = render(format, &output) {
                 debug_println!("{:?}", err);
             }
         }
-        Commands::Clean {} => {
-            if let Err(err) = remove_dir_and_files(CONFIG_DIR) {
+        Commands::Tx { signature } => {
+            let (client, program_id_pk, market_pk) = connect();
+
+            let signature = match Signature::from_str(&signature) {
+                Ok(signature) => signature,
+                Err(err) => {
+                    exit_with_error(format, format!("Invalid signature: {}", err));
+                }
+            };
+
+            let mut labels = BTreeMap::new();
+            let market_keys = get_cached_keys_for_market(&client, &program_id_pk, &market_pk);
+            if let Ok(market_keys) = market_keys {
+                labels.insert(*market_keys.coin_vault, String::from("coin vault"));
+                labels.insert(*market_keys.pc_vault, String::from("pc vault"));
+            }
+
+            let path = CONFIG_DIR.to_string() + "/" + WALLET;
+            let wallet = read_file(path.as_str()).map(|path| read_keypair_file(&path));
+            if let Ok(Ok(wallet)) = wallet {
+                labels.insert(wallet.pubkey(), String::from("wallet"));
+            }
+
+            match inspect_transaction(&client, &program_id_pk, &signature, &labels) {
+                Ok(output) => {
+                    if let Err(err) = render(format, &output) {
+                        debug_println!("{:?}", err);
+                    }
+                }
+                Err(err) => {
+                    exit_with_error(format, format!("Unable to inspect transaction: {}", err))
+                }
+            }
+        }
+        Commands::Tui {
+            wallet,
+            coin_mint,
+            pc_mint,
+            interval,
+        } => {
+            let (client, program_id_pk, market_pk) = connect();
+            let owner = read_keypair_file(&wallet).unwrap();
+
+            let session = match TradingSession::new(&client, &program_id_pk, &market_pk, &owner) {
+                Ok(session) => session,
+                Err(err) => {
+                    exit_with_error(format, format!("Unable to load the market: {}", err));
+                }
+            };
+
+            let coin_mint = Pubkey::from_str(coin_mint.as_str()).unwrap();
+            let pc_mint = Pubkey::from_str(pc_mint.as_str()).unwrap();
+            if let Err(err) = session.check_mints(&coin_mint, &pc_mint) {
+                exit_with_error(format, format!("Invalid mint: {}", err));
+            }
+
+            if let Err(err) = run_terminal(session, Duration::from_secs(interval.max(1))) {
+                exit_with_error(format, format!("Terminal UI failed: {}", err));
+            }
+        }
+        Commands::Shell {
+            wallet,
+            coin_mint,
+            pc_mint,
+        } => {
+            let (client, program_id_pk, market_pk) = connect();
+            let owner = read_keypair_file(&wallet).unwrap();
+
+            let mut session = match TradingSession::new(&client, &program_id_pk, &market_pk, &owner)
+            {
+                Ok(session) => session,
+                Err(err) => {
+                    exit_with_error(format, format!("Unable to load the market: {}", err));
+                }
+            };
+
+            let coin_mint = Pubkey::from_str(coin_mint.as_str()).unwrap();
+            let pc_mint = Pubkey::from_str(pc_mint.as_str()).unwrap();
+            if let Err(err) = session.check_mints(&coin_mint, &pc_mint) {
+                exit_with_error(format, format!("Invalid mint: {}", err));
+            }
+
+            if let Err(err) = run_shell(&mut session, format) {
+                exit_with_error(format, format!("Shell failed: {}", err));
+            }
+        }
+        Commands::Clean { force, dry_run } => {
+            let files = match list_files(CONFIG_DIR) {
+                Ok(files) => files,
+                Err(_) => {
+                    exit_with_error(format, "There are no config files to remove");
+                }
+            };
+
+            let warnings = check_open_orders_before_clean(&files);
+
+            let mut output = CleanOutput {
+                files: files
+                    .iter()
+                    .map(|file_name| format!("{}/{}", CONFIG_DIR, file_name))
+                    .collect(),
+                removed: false,
+                dry_run,
+                backup: None,
+                warnings,
+            };
+
+            if (output.warnings.is_empty() || force) && !dry_run {
+                match backup_dir(CONFIG_DIR, BACKUP_DIR) {
+                    Ok(archive) => output.backup = Some(archive),
+                    Err(err) => {
+                        exit_with_error(format, format!("Unable to back up config files: {}", err));
+                    }
+                }
+
+                match remove_dir_and_files(CONFIG_DIR) {
+                    Ok(()) => output.removed = true,
+                    Err(err) => {
+                        debug_println!("{:?}", err);
+                    }
+                }
+            }
+
+            if let Err(err) = render(format, &output) {
                 debug_println!("{:?}", err);
             }
         }
+        Commands::Restore { archive } => {
+            let archive = match archive.or_else(|| latest_backup(CONFIG_DIR, BACKUP_DIR)) {
+                Some(archive) => archive,
+                None => {
+                    exit_with_error(format, "There is no backup to restore");
+                }
+            };
+
+            match restore_dir(&archive, CONFIG_DIR) {
+                Ok(files) => {
+                    let output = RestoreOutput {
+                        archive,
+                        files: files
+                            .iter()
+                            .map(|file_name| format!("{}/{}", CONFIG_DIR, file_name))
+                            .collect(),
+                    };
+
+                    if let Err(err) = render(format, &output) {
+                        debug_println!("{:?}", err);
+                    }
+                }
+                Err(err) => {
+                    exit_with_error(format, format!("Unable to restore config files: {}", err))
+                }
+            }
+        }
     }
 }
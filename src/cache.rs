@@ -0,0 +1,278 @@
+use std::{path::Path, str::FromStr};
+
+use anyhow::{format_err, Result};
+use debug_print::debug_println;
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{
+    check_market_keys, get_mint_decimals, load_market, load_market_state, read_file, write_file,
+    MarketInfo, MarketPubkeys, CONFIG_DIR, MARKET_CACHE,
+};
+
+/// Addresses and parameters of a market which never change once it's created. They're
+/// cached in the config by `init` and `attach`, so trading commands don't have to fetch
+/// and decode the market account just to learn them.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MarketCache {
+    pub program_id: String,
+    pub market: String,
+    pub req_q: String,
+    pub event_q: String,
+    pub bids: String,
+    pub asks: String,
+    pub coin_vault: String,
+    pub pc_vault: String,
+    pub vault_signer_key: String,
+    pub coin_mint: String,
+    pub pc_mint: String,
+    pub coin_lot_size: u64,
+    pub pc_lot_size: u64,
+    pub coin_decimals: u8,
+    pub pc_decimals: u8,
+}
+
+impl MarketCache {
+    pub fn new(
+        program_id: &Pubkey,
+        keys: &MarketPubkeys,
+        info: &MarketInfo,
+        coin_decimals: u8,
+        pc_decimals: u8,
+    ) -> Self {
+        MarketCache {
+            program_id: program_id.to_string(),
+            market: keys.market.to_string(),
+            req_q: keys.req_q.to_string(),
+            event_q: keys.event_q.to_string(),
+            bids: keys.bids.to_string(),
+            asks: keys.asks.to_string(),
+            coin_vault: keys.coin_vault.to_string(),
+            pc_vault: keys.pc_vault.to_string(),
+            vault_signer_key: keys.vault_signer_key.to_string(),
+            coin_mint: info.coin_mint.to_string(),
+            pc_mint: info.pc_mint.to_string(),
+            coin_lot_size: info.coin_lot_size,
+            pc_lot_size: info.pc_lot_size,
+            coin_decimals,
+            pc_decimals,
+        }
+    }
+
+    /// Builds the cache from the keys and parameters of a market, fetching the decimals
+    /// of its mints.
+    pub fn from_info(
+        client: &RpcClient,
+        program_id: &Pubkey,
+        keys: &MarketPubkeys,
+        info: &MarketInfo,
+    ) -> Result<Self> {
+        Ok(MarketCache::new(
+            program_id,
+            keys,
+            info,
+            get_mint_decimals(client, &info.coin_mint)?,
+            get_mint_decimals(client, &info.pc_mint)?,
+        ))
+    }
+
+    /// Decodes the market account and builds the cache from its current state.
+    pub fn fetch(client: &RpcClient, program_id: &Pubkey, market: &Pubkey) -> Result<Self> {
+        let (keys, info) = load_market(client, program_id, market)?;
+
+        MarketCache::from_info(client, program_id, &keys, &info)
+    }
+
+    /// Loads the cache from the config, `None` if there is none yet.
+    pub fn load(dir_name: &str) -> Result<Option<Self>> {
+        let path = Path::new(dir_name).join(MARKET_CACHE);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let content = read_file(path.to_str().unwrap()).map_err(|err| format_err!(err))?;
+        Ok(Some(serde_json::from_str(&content)?))
+    }
+
+    pub fn save(&self, dir_name: &str) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        write_file(dir_name, MARKET_CACHE, &content).map_err(|err| format_err!(err))
+    }
+
+    /// Whether the cache was made for the given market of the given program.
+    pub fn is_for(&self, program_id: &Pubkey, market: &Pubkey) -> bool {
+        self.program_id == program_id.to_string() && self.market == market.to_string()
+    }
+
+    /// Names of the fields which differ from `other`, empty if both caches match.
+    pub fn mismatches(&self, other: &MarketCache) -> Vec<&'static str> {
+        let fields = [
+            ("program_id", self.program_id == other.program_id),
+            ("market", self.market == other.market),
+            ("req_q", self.req_q == other.req_q),
+            ("event_q", self.event_q == other.event_q),
+            ("bids", self.bids == other.bids),
+            ("asks", self.asks == other.asks),
+            ("coin_vault", self.coin_vault == other.coin_vault),
+            ("pc_vault", self.pc_vault == other.pc_vault),
+            (
+                "vault_signer_key",
+                self.vault_signer_key == other.vault_signer_key,
+            ),
+            ("coin_mint", self.coin_mint == other.coin_mint),
+            ("pc_mint", self.pc_mint == other.pc_mint),
+            ("coin_lot_size", self.coin_lot_size == other.coin_lot_size),
+            ("pc_lot_size", self.pc_lot_size == other.pc_lot_size),
+            ("coin_decimals", self.coin_decimals == other.coin_decimals),
+            ("pc_decimals", self.pc_decimals == other.pc_decimals),
+        ];
+
+        fields
+            .iter()
+            .filter(|(_, matches)| !matches)
+            .map(|(name, _)| *name)
+            .collect()
+    }
+
+    pub fn market_keys(&self) -> Result<MarketPubkeys> {
+        let parse = |name: &str, value: &str| {
+            Pubkey::from_str(value)
+                .map(Box::new)
+                .map_err(|err| format_err!("invalid {} in the market cache: {}", name, err))
+        };
+
+        Ok(MarketPubkeys {
+            market: parse("market", &self.market)?,
+            req_q: parse("req_q", &self.req_q)?,
+            event_q: parse("event_q", &self.event_q)?,
+            bids: parse("bids", &self.bids)?,
+            asks: parse("asks", &self.asks)?,
+            coin_vault: parse("coin_vault", &self.coin_vault)?,
+            pc_vault: parse("pc_vault", &self.pc_vault)?,
+            vault_signer_key: parse("vault_signer_key", &self.vault_signer_key)?,
+        })
+    }
+}
+
+/// Loads the market cache from the config. A missing cache, or one made for another
+/// market or program, is refetched from the market account and saved.
+pub fn load_market_cache(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    market: &Pubkey,
+) -> Result<MarketCache> {
+    match MarketCache::load(CONFIG_DIR) {
+        Ok(Some(cache)) if cache.is_for(program_id, market) => return Ok(cache),
+        Ok(Some(_)) => {
+            debug_println!("Market cache is for another market, refetching it");
+        }
+        Ok(None) => {
+            debug_println!("No market cache found, fetching it");
+        }
+        Err(err) => {
+            debug_println!("Unable to read the market cache: {:?}", err);
+        }
+    }
+
+    let cache = MarketCache::fetch(client, program_id, market)?;
+    cache.save(CONFIG_DIR)?;
+
+    Ok(cache)
+}
+
+/// Refetches the market cache and saves it. Returns the fields in which the previous
+/// cache of the same market didn't match the market account.
+pub fn refresh_market_cache(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    market: &Pubkey,
+) -> Result<(MarketCache, Vec<&'static str>)> {
+    let cache = MarketCache::fetch(client, program_id, market)?;
+
+    let stale = match MarketCache::load(CONFIG_DIR) {
+        Ok(Some(previous)) if previous.is_for(program_id, market) => previous.mismatches(&cache),
+        _ => Vec::new(),
+    };
+    cache.save(CONFIG_DIR)?;
+
+    Ok((cache, stale))
+}
+
+/// Same as `load_market_cache`, but the cached keys are checked against the market account.
+/// Commands which don't load the market through a `MarketSnapshot`, which checks them as
+/// well, use this so a stale cache isn't used.
+pub fn load_checked_market_cache(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    market: &Pubkey,
+) -> Result<MarketCache> {
+    let cache = load_market_cache(client, program_id, market)?;
+    check_market_keys(&cache.market_keys()?, &load_market_state(client, market)?)?;
+
+    Ok(cache)
+}
+
+/// Same as `get_keys_for_market`, but taken from the market cache when there is one. The
+/// cached keys are checked against the market account, see `load_checked_market_cache`.
+pub fn get_cached_keys_for_market(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    market: &Pubkey,
+) -> Result<MarketPubkeys> {
+    load_checked_market_cache(client, program_id, market)?.market_keys()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn market_cache() -> MarketCache {
+        let keys = MarketPubkeys {
+            market: Box::new(Pubkey::new_unique()),
+            req_q: Box::new(Pubkey::new_unique()),
+            event_q: Box::new(Pubkey::new_unique()),
+            bids: Box::new(Pubkey::new_unique()),
+            asks: Box::new(Pubkey::new_unique()),
+            coin_vault: Box::new(Pubkey::new_unique()),
+            pc_vault: Box::new(Pubkey::new_unique()),
+            vault_signer_key: Box::new(Pubkey::new_unique()),
+        };
+        let info = MarketInfo {
+            coin_mint: Pubkey::new_unique(),
+            pc_mint: Pubkey::new_unique(),
+            coin_lot_size: 1_000_000,
+            pc_lot_size: 10_000,
+            fee_rate_bps: 0,
+        };
+
+        MarketCache::new(&Pubkey::new_unique(), &keys, &info, 6, 6)
+    }
+
+    #[test]
+    fn test_market_cache_keys_ok() {
+        let cache = market_cache();
+        let keys = cache.market_keys().unwrap();
+
+        assert_eq!(keys.bids.to_string(), cache.bids);
+        assert_eq!(keys.vault_signer_key.to_string(), cache.vault_signer_key);
+
+        let program_id = Pubkey::from_str(&cache.program_id).unwrap();
+        assert_eq!(cache.is_for(&program_id, &keys.market), true);
+        assert_eq!(cache.is_for(&Pubkey::new_unique(), &keys.market), false);
+    }
+
+    #[test]
+    fn test_market_cache_mismatches() {
+        let cache = market_cache();
+        assert_eq!(cache.mismatches(&cache).is_empty(), true);
+
+        let mut other = cache.clone();
+        other.asks = Pubkey::new_unique().to_string();
+        other.pc_decimals = 9;
+        assert_eq!(cache.mismatches(&other), vec!["asks", "pc_decimals"]);
+
+        other.bids = String::from("not a pubkey");
+        assert_eq!(other.market_keys().is_err(), true);
+    }
+}
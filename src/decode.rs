@@ -0,0 +1,290 @@
+use std::mem::size_of;
+
+use anyhow::{format_err, Result};
+use bytemuck::Pod;
+use serum_dex::{
+    critbit::{AnyNode, LeafNode, NodeHandle},
+    state::{AccountFlag, Event, Market, MarketState, MarketStateV2, OpenOrders, Request},
+};
+
+use crate::{remove_dex_account_padding, QueueHeader};
+
+/// Size of the header at the start of a slab, followed by its nodes.
+pub const SLAB_HEADER_LEN: usize = 32;
+
+/// Reads a `T` from the start of `bytes`. The data of a dex account starts right after
+/// the 5 byte head padding, which leaves it unaligned for `T`, so the value is copied.
+fn read_pod<T: Pod>(bytes: &[u8], name: &str) -> Result<T> {
+    let bytes = bytes
+        .get(..size_of::<T>())
+        .ok_or_else(|| format_err!("{} account is too small", name))?;
+
+    Ok(bytemuck::pod_read_unaligned(bytes))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    let word = bytes.get(offset..offset + size_of::<u64>())?;
+    Some(u64::from_le_bytes(word.try_into().unwrap()))
+}
+
+#[cfg(target_endian = "little")]
+pub fn decode_market_state(account_data: &[u8]) -> Result<MarketState> {
+    let data = remove_dex_account_padding(account_data)?;
+
+    let account_flags = Market::account_flags(account_data)?;
+    if account_flags.intersects(AccountFlag::Permissioned) {
+        let state = read_pod::<MarketStateV2>(data, "market")?;
+        state.check_flags(true)?;
+        Ok(state.inner)
+    } else {
+        let state = read_pod::<MarketState>(data, "market")?;
+        state.check_flags(true)?;
+        Ok(state)
+    }
+}
+
+#[cfg(target_endian = "little")]
+pub fn decode_open_orders(account_data: &[u8]) -> Result<OpenOrders> {
+    read_pod(remove_dex_account_padding(account_data)?, "open orders")
+}
+
+/// Event or request queue borrowed from the account data. The ring buffer isn't copied,
+/// only the entries which are read are, one at a time.
+pub struct QueueView<'a, T: Pod> {
+    pub header: QueueHeader,
+    /// Ring buffer of the queue, a whole number of entries long.
+    entries: &'a [u8],
+}
+
+impl<'a, T: Pod> QueueView<'a, T> {
+    pub fn len(&self) -> usize {
+        self.header.count as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.header.count == 0
+    }
+
+    /// Entries currently in the queue, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        let head = self.header.head as usize;
+        let capacity = self.entries.len() / size_of::<T>();
+        (0..self.len()).map(move |i| {
+            let start = (head + i) % capacity * size_of::<T>();
+            bytemuck::pod_read_unaligned(&self.entries[start..start + size_of::<T>()])
+        })
+    }
+
+    pub fn to_vec(&self) -> Vec<T> {
+        self.iter().collect()
+    }
+}
+
+#[cfg(target_endian = "little")]
+pub fn decode_event_queue(account_data: &[u8]) -> Result<QueueView<Event>> {
+    decode_queue(account_data, "event")
+}
+
+#[cfg(target_endian = "little")]
+pub fn decode_request_queue(account_data: &[u8]) -> Result<QueueView<Request>> {
+    decode_queue(account_data, "request")
+}
+
+#[cfg(target_endian = "little")]
+fn decode_queue<'a, T: Pod>(account_data: &'a [u8], name: &str) -> Result<QueueView<'a, T>> {
+    let data = remove_dex_account_padding(account_data)?;
+
+    // header layout: account_flags, head, count, seq_num
+    let header_len = 4 * size_of::<u64>();
+    let header = match (read_u64(data, 8), read_u64(data, 16), read_u64(data, 24)) {
+        (Some(head), Some(count), Some(seq_num)) => QueueHeader {
+            head,
+            count,
+            seq_num,
+        },
+        _ => {
+            return Err(format_err!(
+                "{} queue is too small to contain a header",
+                name
+            ))
+        }
+    };
+
+    let buf = &data[header_len..];
+    let capacity = buf.len() / size_of::<T>();
+
+    if capacity == 0 || header.head as usize >= capacity || header.count as usize > capacity {
+        return Err(format_err!("{} queue header is corrupted", name));
+    }
+
+    Ok(QueueView {
+        header,
+        entries: &buf[..capacity * size_of::<T>()],
+    })
+}
+
+/// Read-only view of the critbit tree of a bids or asks account.
+pub struct SlabReader<'a> {
+    root: Option<NodeHandle>,
+    leaf_count: u64,
+    nodes: &'a [u8],
+}
+
+impl<'a> SlabReader<'a> {
+    /// Reads a slab starting with its header. The DEX doesn't expose the root, so it's
+    /// read from the header directly.
+    pub fn new(slab_data: &'a [u8]) -> Result<Self> {
+        if slab_data.len() < SLAB_HEADER_LEN {
+            return Err(format_err!("slab is too small to contain a header"));
+        }
+
+        let (header, nodes) = slab_data.split_at(SLAB_HEADER_LEN);
+        let root_node = u32::from_le_bytes(header[20..24].try_into().unwrap());
+        let leaf_count = read_u64(header, 24).unwrap();
+
+        Ok(SlabReader {
+            root: if leaf_count == 0 {
+                None
+            } else {
+                Some(root_node)
+            },
+            leaf_count,
+            nodes,
+        })
+    }
+
+    /// Handle of the root node, or `None` if the slab holds no orders.
+    pub fn root(&self) -> Option<NodeHandle> {
+        self.root
+    }
+
+    pub fn node(&self, handle: NodeHandle) -> Option<AnyNode> {
+        let start = handle as usize * size_of::<AnyNode>();
+        let bytes = self.nodes.get(start..start + size_of::<AnyNode>())?;
+        Some(bytemuck::pod_read_unaligned(bytes))
+    }
+
+    /// Walks the tree from the root and returns its leaves sorted by key, in descending
+    /// order if `descending` is set, stopping after `limit` leaves.
+    ///
+    /// Keys start with the price, so this gives asks best rate first in ascending order
+    /// and bids best rate first in descending order, orders at the same rate by time
+    /// priority.
+    ///
+    /// A tree holding `leaf_count` leaves has `2 * leaf_count - 1` nodes, so a corrupted
+    /// slab which makes the walk visit more nodes than that is reported as an error.
+    pub fn leaves(&self, descending: bool, limit: Option<usize>) -> Result<Vec<LeafNode>> {
+        let limit = limit.unwrap_or(usize::MAX);
+        let max_visits = self.leaf_count.saturating_mul(2).saturating_sub(1);
+        let mut visits = 0u64;
+        let mut leaves = Vec::new();
+        let mut stack: Vec<NodeHandle> = self.root.into_iter().collect();
+
+        while let Some(handle) = stack.pop() {
+            if leaves.len() >= limit {
+                break;
+            }
+
+            visits += 1;
+            if visits > max_visits {
+                return Err(format_err!(
+                    "slab has more nodes than its {} leaves allow",
+                    self.leaf_count
+                ));
+            }
+
+            let node = match self.node(handle) {
+                Some(node) => node,
+                None => continue,
+            };
+
+            if let Some(leaf) = node.as_leaf() {
+                leaves.push(*leaf);
+            } else if let Some([lower, upper]) = node.children() {
+                // the child visited first is pushed last
+                if descending {
+                    stack.push(lower);
+                    stack.push(upper);
+                } else {
+                    stack.push(upper);
+                    stack.push(lower);
+                }
+            }
+        }
+
+        Ok(leaves)
+    }
+}
+
+/// Reads the slab of a bids or asks account, `side` being `AccountFlag::Bids` or
+/// `AccountFlag::Asks`.
+#[cfg(target_endian = "little")]
+pub fn decode_slab(account_data: &[u8], side: AccountFlag) -> Result<SlabReader> {
+    let data = remove_dex_account_padding(account_data)?;
+
+    let account_flags = Market::account_flags(account_data)?;
+    if !account_flags.contains(AccountFlag::Initialized | side) {
+        return Err(format_err!(
+            "account is not an initialized bids or asks account"
+        ));
+    }
+
+    SlabReader::new(&data[size_of::<u64>()..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serum_dex::{critbit::Slab, fees::FeeTier};
+
+    #[test]
+    fn test_slab_reader_leaves_in_order_ok() {
+        let mut words = vec![0u64; (SLAB_HEADER_LEN + 16 * size_of::<LeafNode>()) / 8];
+
+        {
+            let slab = Slab::new(bytemuck::cast_slice_mut(&mut words));
+            for (seq_num, price) in [7u128, 3, 5, 3].iter().enumerate() {
+                let key = (price << 64) | seq_num as u128;
+                let leaf = LeafNode::new(0, key, [0; 4], 1, FeeTier::Base, seq_num as u64);
+                slab.insert_leaf(&leaf).unwrap();
+            }
+        }
+
+        let slab = SlabReader::new(bytemuck::cast_slice(&words)).unwrap();
+        assert_eq!(slab.root().is_some(), true);
+
+        let prices = |leaves: Vec<LeafNode>| -> Vec<u64> {
+            leaves.iter().map(|leaf| leaf.price().get()).collect()
+        };
+
+        let ascending = slab.leaves(false, None).unwrap();
+        assert_eq!(ascending[0].client_order_id(), 1);
+        assert_eq!(prices(ascending), vec![3, 3, 5, 7]);
+        assert_eq!(prices(slab.leaves(true, None).unwrap()), vec![7, 5, 3, 3]);
+        assert_eq!(prices(slab.leaves(false, Some(2)).unwrap()), vec![3, 3]);
+
+        let empty = vec![0u64; SLAB_HEADER_LEN / 8];
+        let slab = SlabReader::new(bytemuck::cast_slice(&empty)).unwrap();
+        assert_eq!(slab.leaves(false, None).unwrap().is_empty(), true);
+    }
+
+    #[test]
+    fn test_slab_reader_leaves_too_many_nodes_failed() {
+        let mut words = vec![0u64; (SLAB_HEADER_LEN + 16 * size_of::<LeafNode>()) / 8];
+
+        {
+            let slab = Slab::new(bytemuck::cast_slice_mut(&mut words));
+            for (seq_num, price) in [7u128, 3, 5].iter().enumerate() {
+                let key = (price << 64) | seq_num as u128;
+                let leaf = LeafNode::new(0, key, [0; 4], 1, FeeTier::Base, seq_num as u64);
+                slab.insert_leaf(&leaf).unwrap();
+            }
+        }
+
+        // the header claims fewer leaves than the tree holds
+        words[3] = 1;
+
+        let slab = SlabReader::new(bytemuck::cast_slice(&words)).unwrap();
+        assert_eq!(slab.leaves(false, None).is_err(), true);
+    }
+}
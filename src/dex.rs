@@ -1,18 +1,36 @@
 use anyhow::{format_err, Result};
+use std::mem::size_of;
 use std::str::FromStr;
-use std::{borrow::Cow, mem::size_of};
 
 use rand::rngs::OsRng;
 use safe_transmute::*;
 use std::convert::identity;
 
-use crate::{read_file, write_file, MarketPubkeys, CONFIG_DIR, OPEN_ORDER};
-use serum_dex::instruction::init_open_orders as init_open_orders_ix;
-use serum_dex::state::{gen_vault_signer_key, AccountFlag, Market, MarketState, MarketStateV2};
+use crate::{
+    decode_event_queue, decode_market_state, decode_open_orders, decode_request_queue,
+    open_order_slots, read_file, settle_funds_instruction, write_file, MarketPubkeys, CONFIG_DIR,
+    OPEN_ORDER,
+};
+use serum_dex::instruction::{
+    close_open_orders as close_open_orders_ix, init_open_orders as init_open_orders_ix,
+};
+use serum_dex::state::{
+    gen_vault_signer_key, Event, MarketState, OpenOrders, Request, ACCOUNT_HEAD_PADDING,
+    ACCOUNT_TAIL_PADDING,
+};
 
-use solana_client::rpc_client::RpcClient;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    rpc_client::RpcClient,
+    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+    rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType},
+};
 use solana_sdk::{
-    instruction::Instruction, pubkey::Pubkey, signature::Keypair, signer::Signer,
+    account::Account,
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
     transaction::Transaction,
 };
 
@@ -36,82 +54,256 @@ pub fn create_dex_account(
     Ok((key, create_account_instr))
 }
 
+/// OpenOrders account of a wallet as found or set up by `get_open_order_pubkey`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenOrdersAccount {
+    pub address: Pubkey,
+    /// Signature of the transaction which created the account, if it had to be created.
+    pub signature: Option<Signature>,
+    /// Warnings for the user, set when the account was missing in the config and an
+    /// existing one was re-adopted.
+    pub warnings: Vec<String>,
+}
+
+/// Returns the OpenOrders account of the owner, re-adopting or creating one if none
+/// is recorded. Nothing is printed, the caller shows the warnings.
 pub fn get_open_order_pubkey(
     client: &RpcClient,
     program_id: &Pubkey,
     owner: &Keypair,
     state: &MarketPubkeys,
-) -> Result<Pubkey> {
-    let mut orders = None;
-    let path =
-        CONFIG_DIR.to_string() + "/" + owner.pubkey().to_string().as_str() + "_" + OPEN_ORDER;
+) -> Result<OpenOrdersAccount> {
+    let path = open_order_path(&owner.pubkey());
 
     let result = read_file(path.as_str());
 
     if result.is_err() {
-        if let Err(err) = init_open_orders(&client, &program_id, &owner, &state, &mut orders) {
-            panic!("{:?}", err);
-        } else {
-            let file_name = owner.pubkey().to_string() + "_" + OPEN_ORDER;
-            write_file(CONFIG_DIR, &file_name, orders.unwrap().to_string().as_str()).unwrap();
-
-            return Ok(orders.unwrap());
+        let existing =
+            find_open_orders_accounts(client, program_id, &state.market, &owner.pubkey())?;
+
+        if let Some(existing_orders) = existing.first() {
+            let mut warnings = vec![format!(
+                "open orders account is missing in the config, re-adopting {}",
+                existing_orders
+            )];
+            if existing.len() > 1 {
+                warnings.push(format!(
+                    "the wallet owns {} open orders accounts on this market, \
+                    run recover-open-orders to choose another one",
+                    existing.len()
+                ));
+            }
+
+            adopt_open_orders(&owner.pubkey(), existing_orders)?;
+            return Ok(OpenOrdersAccount {
+                address: *existing_orders,
+                signature: None,
+                warnings,
+            });
         }
+
+        let (orders, signature) = init_open_orders(client, program_id, owner, state)?;
+        adopt_open_orders(&owner.pubkey(), &orders)?;
+
+        return Ok(OpenOrdersAccount {
+            address: orders,
+            signature: Some(signature),
+            warnings: Vec::new(),
+        });
+    }
+
+    Ok(OpenOrdersAccount {
+        address: Pubkey::from_str(result.unwrap().as_str())?,
+        signature: None,
+        warnings: Vec::new(),
+    })
+}
+
+fn open_order_path(owner: &Pubkey) -> String {
+    CONFIG_DIR.to_string() + "/" + owner.to_string().as_str() + "_" + OPEN_ORDER
+}
+
+/// Reads the OpenOrders account of the owner recorded in the config without creating a new one.
+pub fn read_open_order_pubkey(owner: &Pubkey) -> Result<Pubkey> {
+    let path = open_order_path(owner);
+    let content = read_file(path.as_str()).map_err(|err| format_err!(err))?;
+
+    Ok(Pubkey::from_str(content.as_str())?)
+}
+
+/// Records the OpenOrders account of the owner in the config.
+pub fn adopt_open_orders(owner: &Pubkey, orders: &Pubkey) -> Result<()> {
+    let file_name = owner.to_string() + "_" + OPEN_ORDER;
+    write_file(CONFIG_DIR, &file_name, orders.to_string().as_str())
+        .map_err(|err| format_err!(err))
+}
+
+/// Removes the OpenOrders account of the owner from the config.
+pub fn forget_open_orders(owner: &Pubkey) -> Result<()> {
+    std::fs::remove_file(open_order_path(owner))?;
+    Ok(())
+}
+
+/// Checks that the OpenOrders account can be closed: it mustn't hold any orders
+/// and, unless its free balances are settled first, any funds.
+pub fn check_open_orders_closable(open_orders: &OpenOrders, settle: bool) -> Result<()> {
+    let orders = open_order_slots(open_orders);
+    if !orders.is_empty() {
+        return Err(format_err!(
+            "open orders account still has {} order(s) in the book, cancel them first",
+            orders.len()
+        ));
+    }
+
+    let native_coin_total = open_orders.native_coin_total;
+    let native_pc_total = open_orders.native_pc_total;
+    let native_coin_free = open_orders.native_coin_free;
+    let native_pc_free = open_orders.native_pc_free;
+
+    let (coin, pc) = if settle {
+        (
+            native_coin_total.saturating_sub(native_coin_free),
+            native_pc_total.saturating_sub(native_pc_free),
+        )
+    } else {
+        (native_coin_total, native_pc_total)
+    };
+
+    if coin != 0 || pc != 0 {
+        return Err(format_err!(
+            "open orders account still holds {} coin and {} pc, settle funds first",
+            coin,
+            pc
+        ));
     }
 
-    Ok(Pubkey::from_str(result.unwrap().as_str())?)
+    Ok(())
 }
 
-fn init_open_orders(
+/// Closes the OpenOrders account and sends its rent lamports to the destination.
+/// When `settle_wallets` are given, free funds are settled to them in the same transaction.
+pub fn close_open_orders(
     client: &RpcClient,
     program_id: &Pubkey,
     owner: &Keypair,
     state: &MarketPubkeys,
-    orders: &mut Option<Pubkey>,
-) -> Result<()> {
+    orders: &Pubkey,
+    destination: &Pubkey,
+    settle_wallets: Option<(&Pubkey, &Pubkey)>,
+) -> Result<Signature> {
+    let open_orders = load_open_orders(client, orders)?;
+    check_open_orders_closable(&open_orders, settle_wallets.is_some())?;
+
     let mut instructions = Vec::new();
-    let mut signers = Vec::new();
-
-    let orders_keypair;
-
-    let orders_pubkey = match *orders {
-        Some(pk) => pk,
-        None => {
-            let (orders_key, instruction) = create_dex_account(
-                client,
-                program_id,
-                &owner.pubkey(),
-                size_of::<serum_dex::state::OpenOrders>(),
-            )?;
-            orders_keypair = orders_key;
-            signers.push(&orders_keypair);
-            instructions.push(instruction);
-            orders_keypair.pubkey()
-        }
-    };
 
-    *orders = Some(orders_pubkey);
+    if let Some((coin_wallet, pc_wallet)) = settle_wallets {
+        instructions.push(settle_funds_instruction(
+            program_id,
+            state,
+            orders,
+            &owner.pubkey(),
+            coin_wallet,
+            pc_wallet,
+        )?);
+    }
 
-    instructions.push(init_open_orders_ix(
+    instructions.push(close_open_orders_ix(
         program_id,
-        &orders_pubkey,
+        orders,
         &owner.pubkey(),
+        destination,
         &state.market,
-        None,
     )?);
 
-    signers.push(owner);
+    let recent_hash = client.get_latest_blockhash()?;
+    let txn = Transaction::new_signed_with_payer(
+        &instructions,
+        Some(&owner.pubkey()),
+        &[owner],
+        recent_hash,
+    );
+
+    let signature = client.send_and_confirm_transaction(&txn)?;
+
+    Ok(signature)
+}
+
+/// Finds the OpenOrders accounts of the owner on the market with `getProgramAccounts`.
+pub fn find_open_orders_accounts(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    market: &Pubkey,
+    owner: &Pubkey,
+) -> Result<Vec<Pubkey>> {
+    // account layout: head padding, account_flags, market, owner
+    let market_offset = ACCOUNT_HEAD_PADDING.len() + size_of::<u64>();
+    let owner_offset = market_offset + size_of::<Pubkey>();
+
+    let filters = vec![
+        RpcFilterType::DataSize((size_of::<OpenOrders>() + 12) as u64),
+        RpcFilterType::Memcmp(Memcmp {
+            offset: market_offset,
+            bytes: MemcmpEncodedBytes::Base58(market.to_string()),
+            encoding: None,
+        }),
+        RpcFilterType::Memcmp(Memcmp {
+            offset: owner_offset,
+            bytes: MemcmpEncodedBytes::Base58(owner.to_string()),
+            encoding: None,
+        }),
+    ];
+
+    let accounts = client.get_program_accounts_with_config(
+        program_id,
+        RpcProgramAccountsConfig {
+            filters: Some(filters),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                ..RpcAccountInfoConfig::default()
+            },
+            with_context: None,
+        },
+    )?;
+
+    Ok(accounts.into_iter().map(|(pubkey, _)| pubkey).collect())
+}
+
+/// Creates and initializes a new OpenOrders account of the owner on the market.
+pub fn init_open_orders(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    owner: &Keypair,
+    state: &MarketPubkeys,
+) -> Result<(Pubkey, Signature)> {
+    let (orders_keypair, create_instruction) = create_dex_account(
+        client,
+        program_id,
+        &owner.pubkey(),
+        size_of::<serum_dex::state::OpenOrders>(),
+    )?;
+    let orders_pubkey = orders_keypair.pubkey();
+
+    let instructions = vec![
+        create_instruction,
+        init_open_orders_ix(
+            program_id,
+            &orders_pubkey,
+            &owner.pubkey(),
+            &state.market,
+            None,
+        )?,
+    ];
 
     let recent_hash = client.get_latest_blockhash()?;
     let txn = Transaction::new_signed_with_payer(
         &instructions,
         Some(&owner.pubkey()),
-        &signers,
+        &[&orders_keypair, owner],
         recent_hash,
     );
 
-    let _signature = client.send_and_confirm_transaction(&txn)?;
-    Ok(())
+    let signature = client.send_and_confirm_transaction(&txn)?;
+    Ok((orders_pubkey, signature))
 }
 
 #[cfg(target_endian = "little")]
@@ -120,32 +312,27 @@ pub fn get_keys_for_market<'a>(
     program_id: &'a Pubkey,
     market: &'a Pubkey,
 ) -> Result<MarketPubkeys> {
-    let account_data: Vec<u8> = client.get_account_data(&market)?;
-    let words: Cow<[u64]> = remove_dex_account_padding(&account_data)?;
-
-    let market_state: MarketState = {
-        let account_flags = Market::account_flags(&account_data)?;
-        if account_flags.intersects(AccountFlag::Permissioned) {
-            let state = transmute_one_pedantic::<MarketStateV2>(transmute_to_bytes(&words))
-                .map_err(|e| e.without_src())?;
-            state.check_flags(true)?;
-            state.inner
-        } else {
-            let state = transmute_one_pedantic::<MarketState>(transmute_to_bytes(&words))
-                .map_err(|e| e.without_src())?;
-            state.check_flags(true)?;
-            state
-        }
-    };
+    let market_state = load_market_state(client, market)?;
+    market_keys_from_state(program_id, market, &market_state)
+}
+
+/// Derives the keys of a market from its decoded state, checking that the state is the
+/// one of the given market account.
+pub fn market_keys_from_state(
+    program_id: &Pubkey,
+    market: &Pubkey,
+    market_state: &MarketState,
+) -> Result<MarketPubkeys> {
+    if transmute_to_bytes(&identity(market_state.own_address)) != market.as_ref() {
+        return Err(format_err!(
+            "market state doesn't belong to the market account {}",
+            market
+        ));
+    }
 
     let vault_signer_key =
         gen_vault_signer_key(market_state.vault_signer_nonce, market, program_id)?;
 
-    assert_eq!(
-        transmute_to_bytes(&identity(market_state.own_address)),
-        market.as_ref()
-    );
-
     Ok(MarketPubkeys {
         market: Box::new(*market),
         req_q: Box::new(Pubkey::new(transmute_one_to_bytes(&identity(
@@ -170,12 +357,211 @@ pub fn get_keys_for_market<'a>(
     })
 }
 
+/// Names of the keys which differ from the ones recorded in the decoded state of their
+/// market, empty if all of them belong to it.
+pub fn stale_market_keys(keys: &MarketPubkeys, market_state: &MarketState) -> Vec<&'static str> {
+    let fields: [(&'static str, &Pubkey, [u64; 4]); 7] = [
+        ("market", &*keys.market, identity(market_state.own_address)),
+        ("req_q", &*keys.req_q, identity(market_state.req_q)),
+        ("event_q", &*keys.event_q, identity(market_state.event_q)),
+        ("bids", &*keys.bids, identity(market_state.bids)),
+        ("asks", &*keys.asks, identity(market_state.asks)),
+        (
+            "coin_vault",
+            &*keys.coin_vault,
+            identity(market_state.coin_vault),
+        ),
+        ("pc_vault", &*keys.pc_vault, identity(market_state.pc_vault)),
+    ];
+
+    fields
+        .iter()
+        .filter(|(_, key, state_key)| key.to_bytes()[..] != *transmute_one_to_bytes(state_key))
+        .map(|(name, _, _)| *name)
+        .collect()
+}
+
+/// Fails with the names of the keys which don't belong to the market, asking to refetch
+/// the market cache they usually come from.
+pub fn check_market_keys(keys: &MarketPubkeys, market_state: &MarketState) -> Result<()> {
+    let stale = stale_market_keys(keys, market_state);
+    if !stale.is_empty() {
+        return Err(format_err!(
+            "cached {} of market {} are stale, run the command with --refresh",
+            stale.join(", "),
+            keys.market
+        ));
+    }
+
+    Ok(())
+}
+
 #[cfg(target_endian = "little")]
-fn remove_dex_account_padding<'a>(data: &'a [u8]) -> Result<Cow<'a, [u64]>> {
-    use serum_dex::state::{ACCOUNT_HEAD_PADDING, ACCOUNT_TAIL_PADDING};
+pub fn load_market_state(client: &RpcClient, market: &Pubkey) -> Result<MarketState> {
+    let account_data: Vec<u8> = client.get_account_data(&market)?;
+    decode_market_state(&account_data)
+}
 
-    let head = &data[..ACCOUNT_HEAD_PADDING.len()];
+#[cfg(target_endian = "little")]
+pub fn load_open_orders(client: &RpcClient, open_orders: &Pubkey) -> Result<OpenOrders> {
+    let account_data: Vec<u8> = client.get_account_data(open_orders)?;
+    decode_open_orders(&account_data)
+}
+
+/// Header shared by the event and request queues, the entries are kept in a ring buffer
+/// following it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueHeader {
+    pub head: u64,
+    pub count: u64,
+    /// Sequence number the next entry pushed to the queue will get.
+    pub seq_num: u64,
+}
+
+/// Loads the events currently stored in the event queue, oldest first.
+#[cfg(target_endian = "little")]
+pub fn load_events(client: &RpcClient, event_q: &Pubkey) -> Result<Vec<Event>> {
+    Ok(load_event_queue(client, event_q)?.1)
+}
+
+/// Loads the header of the event queue together with its events, oldest first.
+#[cfg(target_endian = "little")]
+pub fn load_event_queue(client: &RpcClient, event_q: &Pubkey) -> Result<(QueueHeader, Vec<Event>)> {
+    let account_data = client.get_account_data(event_q)?;
+    let queue = decode_event_queue(&account_data)?;
+    Ok((queue.header, queue.to_vec()))
+}
+
+/// Loads the header of the request queue together with its pending requests, oldest first.
+#[cfg(target_endian = "little")]
+pub fn load_request_queue(
+    client: &RpcClient,
+    req_q: &Pubkey,
+) -> Result<(QueueHeader, Vec<Request>)> {
+    let account_data = client.get_account_data(req_q)?;
+    let queue = decode_request_queue(&account_data)?;
+    Ok((queue.header, queue.to_vec()))
+}
+
+/// Accounts of a market fetched in a single request, so they all reflect the same slot.
+pub struct MarketSnapshot<'a> {
+    pub keys: &'a MarketPubkeys,
+    pub slot: u64,
+    pub market: Account,
+    pub bids: Account,
+    pub asks: Account,
+    pub event_q: Account,
+    pub coin_vault: Account,
+    pub pc_vault: Account,
+    /// OpenOrders account, if one was requested.
+    pub open_orders: Option<Account>,
+}
+
+/// Fetches the market, its bids, asks, event queue and vaults, and optionally an
+/// OpenOrders account, with one `getMultipleAccounts` call. Fails if the keys don't
+/// belong to the market anymore.
+#[cfg(target_endian = "little")]
+pub fn load_market_snapshot<'a>(
+    client: &RpcClient,
+    keys: &'a MarketPubkeys,
+    orders: Option<&Pubkey>,
+) -> Result<MarketSnapshot<'a>> {
+    let mut pubkeys = vec![
+        *keys.market,
+        *keys.bids,
+        *keys.asks,
+        *keys.event_q,
+        *keys.coin_vault,
+        *keys.pc_vault,
+    ];
+    pubkeys.extend(orders);
+
+    let response = client.get_multiple_accounts_with_commitment(&pubkeys, client.commitment())?;
+    let mut accounts = pubkeys.iter().zip(response.value).map(|(pubkey, account)| {
+        account.ok_or_else(|| format_err!("account {} not found", pubkey))
+    });
+    let mut next = || {
+        accounts
+            .next()
+            .unwrap_or_else(|| Err(format_err!("missing accounts in the response")))
+    };
+
+    let market = next()?;
+
+    // the keys usually come from the market cache, which is only refetched with --refresh
+    check_market_keys(keys, &decode_market_state(&market.data)?)?;
+
+    Ok(MarketSnapshot {
+        keys,
+        slot: response.context.slot,
+        market,
+        bids: next()?,
+        asks: next()?,
+        event_q: next()?,
+        coin_vault: next()?,
+        pc_vault: next()?,
+        open_orders: match orders {
+            Some(_) => Some(next()?),
+            None => None,
+        },
+    })
+}
 
+#[cfg(target_endian = "little")]
+impl<'a> MarketSnapshot<'a> {
+    pub fn market_state(&self) -> Result<MarketState> {
+        decode_market_state(&self.market.data)
+    }
+
+    /// Decoded OpenOrders account, `None` if none was requested.
+    pub fn open_orders(&self) -> Result<Option<OpenOrders>> {
+        self.open_orders
+            .as_ref()
+            .map(|account| decode_open_orders(&account.data))
+            .transpose()
+    }
+
+    pub fn event_queue(&self) -> Result<(QueueHeader, Vec<Event>)> {
+        let queue = decode_event_queue(&self.event_q.data)?;
+        Ok((queue.header, queue.to_vec()))
+    }
+
+    pub fn coin_vault_balance(&self) -> Result<u64> {
+        decode_token_amount(&self.coin_vault.data)
+    }
+
+    pub fn pc_vault_balance(&self) -> Result<u64> {
+        decode_token_amount(&self.pc_vault.data)
+    }
+}
+
+fn decode_token_amount(account_data: &[u8]) -> Result<u64> {
+    let account =
+        <spl_token::state::Account as solana_sdk::program_pack::Pack>::unpack(account_data)?;
+    Ok(account.amount)
+}
+
+pub fn get_mint_decimals(client: &RpcClient, mint: &Pubkey) -> Result<u8> {
+    let data = client.get_account_data(mint)?;
+    let mint = <spl_token::state::Mint as solana_sdk::program_pack::Pack>::unpack(&data)?;
+    Ok(mint.decimals)
+}
+
+/// Balance of a token account, 0 if the account doesn't exist yet. Any other failure is
+/// returned, so it isn't mistaken for an empty wallet.
+pub fn get_token_balance(client: &RpcClient, token_account: &Pubkey) -> Result<u64> {
+    match client
+        .get_account_with_commitment(token_account, client.commitment())?
+        .value
+    {
+        Some(account) => decode_token_amount(&account.data),
+        None => Ok(0),
+    }
+}
+
+/// Returns the data of a dex account between its head and tail padding, without copying.
+#[cfg(target_endian = "little")]
+pub fn remove_dex_account_padding(data: &[u8]) -> Result<&[u8]> {
     if data.len() < ACCOUNT_HEAD_PADDING.len() + ACCOUNT_TAIL_PADDING.len() {
         return Err(format_err!(
             "dex account length {} is too small to contain valid padding",
@@ -183,6 +569,8 @@ fn remove_dex_account_padding<'a>(data: &'a [u8]) -> Result<Cow<'a, [u64]>> {
         ));
     }
 
+    let head = &data[..ACCOUNT_HEAD_PADDING.len()];
+
     if head != ACCOUNT_HEAD_PADDING {
         return Err(format_err!("dex account head padding mismatch"));
     }
@@ -194,15 +582,88 @@ fn remove_dex_account_padding<'a>(data: &'a [u8]) -> Result<Cow<'a, [u64]>> {
     }
 
     let inner_data_range = ACCOUNT_HEAD_PADDING.len()..(data.len() - ACCOUNT_TAIL_PADDING.len());
-    let inner: &'a [u8] = &data[inner_data_range];
+    Ok(&data[inner_data_range])
+}
 
-    let words: Cow<'a, [u64]> = match transmute_many_pedantic::<u64>(inner) {
-        Ok(word_slice) => Cow::Borrowed(word_slice),
-        Err(transmute_error) => {
-            let word_vec = transmute_error.copy().map_err(|e| e.without_src())?;
-            Cow::Owned(word_vec)
-        }
-    };
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty_open_orders() -> OpenOrders {
+        let mut open_orders: OpenOrders = bytemuck::Zeroable::zeroed();
+        open_orders.free_slot_bits = std::u128::MAX;
+        open_orders
+    }
 
-    Ok(words)
+    #[test]
+    fn test_remove_dex_account_padding_err() {
+        assert_eq!(remove_dex_account_padding(b"serum").is_err(), true);
+        assert_eq!(
+            remove_dex_account_padding(b"dummy0000padding").is_err(),
+            true
+        );
+        assert_eq!(
+            remove_dex_account_padding(b"serum0000padding").unwrap(),
+            b"0000"
+        );
+    }
+
+    #[test]
+    fn test_stale_market_keys_ok() {
+        let keys = MarketPubkeys {
+            market: Box::new(Pubkey::new_unique()),
+            req_q: Box::new(Pubkey::new_unique()),
+            event_q: Box::new(Pubkey::new_unique()),
+            bids: Box::new(Pubkey::new_unique()),
+            asks: Box::new(Pubkey::new_unique()),
+            coin_vault: Box::new(Pubkey::new_unique()),
+            pc_vault: Box::new(Pubkey::new_unique()),
+            vault_signer_key: Box::new(Pubkey::new_unique()),
+        };
+
+        let mut market_state: MarketState = bytemuck::Zeroable::zeroed();
+        market_state.own_address = bytemuck::cast(keys.market.to_bytes());
+        market_state.req_q = bytemuck::cast(keys.req_q.to_bytes());
+        market_state.event_q = bytemuck::cast(keys.event_q.to_bytes());
+        market_state.bids = bytemuck::cast(keys.bids.to_bytes());
+        market_state.asks = bytemuck::cast(keys.asks.to_bytes());
+        market_state.coin_vault = bytemuck::cast(keys.coin_vault.to_bytes());
+        market_state.pc_vault = bytemuck::cast(keys.pc_vault.to_bytes());
+        assert_eq!(stale_market_keys(&keys, &market_state).is_empty(), true);
+
+        market_state.asks = bytemuck::cast(Pubkey::new_unique().to_bytes());
+        market_state.pc_vault = bytemuck::cast(Pubkey::new_unique().to_bytes());
+        assert_eq!(
+            stale_market_keys(&keys, &market_state),
+            vec!["asks", "pc_vault"]
+        );
+    }
+
+    #[test]
+    fn test_check_open_orders_closable_ok() {
+        let open_orders = empty_open_orders();
+        assert_eq!(check_open_orders_closable(&open_orders, false).is_ok(), true);
+
+        let mut open_orders = empty_open_orders();
+        open_orders.native_coin_free = 10;
+        open_orders.native_coin_total = 10;
+        assert_eq!(check_open_orders_closable(&open_orders, true).is_ok(), true);
+    }
+
+    #[test]
+    fn test_check_open_orders_closable_failed() {
+        let mut open_orders = empty_open_orders();
+        open_orders.native_pc_free = 10;
+        open_orders.native_pc_total = 10;
+        assert_eq!(check_open_orders_closable(&open_orders, false).is_err(), true);
+
+        let mut open_orders = empty_open_orders();
+        open_orders.native_coin_free = 5;
+        open_orders.native_coin_total = 10;
+        assert_eq!(check_open_orders_closable(&open_orders, true).is_err(), true);
+
+        let mut open_orders = empty_open_orders();
+        open_orders.free_slot_bits = std::u128::MAX - 1;
+        assert_eq!(check_open_orders_closable(&open_orders, true).is_err(), true);
+    }
 }
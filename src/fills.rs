@@ -0,0 +1,310 @@
+use std::path::Path;
+
+use anyhow::{format_err, Result};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{read_file, side_label, write_file, EventOutput, OrderSide, FILL_HISTORY};
+
+/// A fill of one of the wallet's orders.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FillRecord {
+    /// Sequence number of the fill event in the event queue.
+    pub seq_num: u64,
+    /// Unix timestamp at which the fill was first seen in the event queue. Fills carry no
+    /// time of their own, so this is when `fills` ran, not when the orders matched.
+    #[serde(alias = "timestamp")]
+    pub first_seen: i64,
+    pub side: OrderSide,
+    pub maker: bool,
+    pub rate: u64,
+    /// Filled size in coin lots.
+    pub size: u64,
+    /// Taker fee paid, or maker rebate earned, in native pc units.
+    pub fee: u64,
+    pub order_id: String,
+    pub client_order_id: Option<u64>,
+    /// OpenOrders accounts of the orders matched against this one.
+    pub counterparties: Vec<String>,
+}
+
+/// Fills of the wallet on one market collected from the event queue over time. Events are
+/// removed from the queue once the market is cranked, so every snapshot is merged into it.
+/// Sequence numbers are only unique within a market, so every market has its own file.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct FillHistory {
+    pub fills: Vec<FillRecord>,
+}
+
+impl FillHistory {
+    pub fn file_name(owner: &Pubkey, market: &Pubkey) -> String {
+        owner.to_string() + "_" + &market.to_string() + "_" + FILL_HISTORY
+    }
+
+    /// Loads the fill history of the given wallet on the market, or an empty one if nothing
+    /// was recorded yet.
+    pub fn load(dir_name: &str, owner: &Pubkey, market: &Pubkey) -> Result<Self> {
+        let path = Path::new(dir_name).join(Self::file_name(owner, market));
+        if !path.exists() {
+            return Ok(FillHistory::default());
+        }
+
+        let content = read_file(path.to_str().unwrap()).map_err(|err| format_err!(err))?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save(&self, dir_name: &str, owner: &Pubkey, market: &Pubkey) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        write_file(dir_name, &Self::file_name(owner, market), &content)
+            .map_err(|err| format_err!(err))
+    }
+
+    /// Adds the fills which aren't recorded yet and returns how many were added.
+    pub fn merge(&mut self, fills: Vec<FillRecord>) -> usize {
+        let mut added = 0;
+
+        for fill in fills {
+            if self.fills.iter().all(|known| known.seq_num != fill.seq_num) {
+                self.fills.push(fill);
+                added += 1;
+            }
+        }
+
+        self.fills.sort_by_key(|fill| fill.seq_num);
+        added
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "first_seen,side,rate,size,fee,maker,order_id,client_order_id,counterparty\n",
+        );
+
+        for fill in self.fills.iter() {
+            csv += &format!(
+                "{},{},{},{},{},{},{},{},{}\n",
+                fill.first_seen,
+                side_label(fill.side),
+                fill.rate,
+                fill.size,
+                fill.fee,
+                fill.maker,
+                fill.order_id,
+                fill.client_order_id
+                    .map(|client_order_id| client_order_id.to_string())
+                    .unwrap_or_default(),
+                fill.counterparties.join(";")
+            );
+        }
+
+        csv
+    }
+}
+
+/// Picks the fills of the OpenOrders account out of the events, which must be in queue order.
+/// Only the events still in the queue are seen, and every fill is stamped with `first_seen`.
+///
+/// While an order is matched the DEX pushes a fill for every maker order it crosses followed
+/// by a single fill for the taker, which is how the counterparties are paired up.
+pub fn fills_from_events(
+    events: &[EventOutput],
+    orders: &Pubkey,
+    coin_lot_size: u64,
+    pc_lot_size: u64,
+    first_seen: i64,
+) -> Vec<FillRecord> {
+    let orders = orders.to_string();
+    let mut fills = Vec::new();
+    let mut makers: Vec<usize> = Vec::new();
+
+    for (index, event) in events.iter().enumerate() {
+        let (seq_num, side, maker, paid, received, fee, order_id, owner, client_order_id) =
+            match event {
+                EventOutput::Fill {
+                    seq_num,
+                    side,
+                    maker,
+                    native_qty_paid,
+                    native_qty_received,
+                    native_fee_or_rebate,
+                    order_id,
+                    owner,
+                    client_order_id,
+                } => (
+                    *seq_num,
+                    *side,
+                    *maker,
+                    *native_qty_paid,
+                    *native_qty_received,
+                    *native_fee_or_rebate,
+                    order_id,
+                    owner,
+                    *client_order_id,
+                ),
+                EventOutput::Out { .. } => continue,
+            };
+
+        // the taker fill closes the current match, so it's paired with the makers before it
+        let counterparties: Vec<String> = if maker {
+            makers.push(index);
+            next_taker(&events[index + 1..]).into_iter().collect()
+        } else {
+            let counterparties = makers
+                .iter()
+                .map(|maker| events[*maker].owner().to_string())
+                .collect();
+            makers.clear();
+            counterparties
+        };
+
+        if *owner != orders {
+            continue;
+        }
+
+        // takers pay fees on top of bids and out of asks, while the rebate of makers is
+        // added to what asks receive and taken off what bids pay
+        let (native_coin_qty, native_pc_qty) = match (side, maker) {
+            (OrderSide::Lend, true) => (paid, received.saturating_sub(fee)),
+            (OrderSide::Borrow, true) => (received, paid + fee),
+            (OrderSide::Lend, false) => (paid, received + fee),
+            (OrderSide::Borrow, false) => (received, paid.saturating_sub(fee)),
+        };
+
+        let size = native_coin_qty / coin_lot_size.max(1);
+        let rate = if size == 0 {
+            0
+        } else {
+            native_pc_qty / pc_lot_size.max(1) / size
+        };
+
+        fills.push(FillRecord {
+            seq_num,
+            first_seen,
+            side,
+            maker,
+            rate,
+            size,
+            fee,
+            order_id: order_id.clone(),
+            client_order_id,
+            counterparties,
+        });
+    }
+
+    fills
+}
+
+fn next_taker(events: &[EventOutput]) -> Option<String> {
+    events.iter().find_map(|event| match event {
+        EventOutput::Fill {
+            maker: false,
+            owner,
+            ..
+        } => Some(owner.clone()),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill(seq_num: u64, side: OrderSide, maker: bool, owner: &Pubkey) -> EventOutput {
+        EventOutput::Fill {
+            seq_num,
+            side,
+            maker,
+            native_qty_paid: 0,
+            native_qty_received: 0,
+            native_fee_or_rebate: 0,
+            order_id: seq_num.to_string(),
+            owner: owner.to_string(),
+            client_order_id: None,
+        }
+    }
+
+    #[test]
+    fn test_fills_from_events_ok() {
+        let lender = Pubkey::new_unique();
+        let borrower = Pubkey::new_unique();
+
+        // a resting lend order of 2 lots at rate 5 is taken by a borrow order
+        let maker = EventOutput::Fill {
+            native_qty_paid: 2_000_000,
+            native_qty_received: 100_030,
+            native_fee_or_rebate: 30,
+            ..fill(1, OrderSide::Lend, true, &lender)
+        };
+        let taker = EventOutput::Fill {
+            native_qty_paid: 100_040,
+            native_qty_received: 2_000_000,
+            native_fee_or_rebate: 40,
+            ..fill(2, OrderSide::Borrow, false, &borrower)
+        };
+        let events = vec![maker, taker];
+
+        let fills = fills_from_events(&events, &lender, 1_000_000, 10_000, 100);
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].seq_num, 1);
+        assert_eq!(fills[0].side, OrderSide::Lend);
+        assert_eq!(fills[0].maker, true);
+        assert_eq!(fills[0].size, 2);
+        assert_eq!(fills[0].rate, 5);
+        assert_eq!(fills[0].counterparties, vec![borrower.to_string()]);
+
+        let fills = fills_from_events(&events, &borrower, 1_000_000, 10_000, 100);
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].rate, 5);
+        assert_eq!(fills[0].fee, 40);
+        assert_eq!(fills[0].counterparties, vec![lender.to_string()]);
+    }
+
+    #[test]
+    fn test_fills_from_events_maker_borrow_ok() {
+        let lender = Pubkey::new_unique();
+        let borrower = Pubkey::new_unique();
+
+        // a resting borrow order of 2 lots at rate 5 pays 100_000 less its rebate of 30
+        let maker = EventOutput::Fill {
+            native_qty_paid: 99_970,
+            native_qty_received: 2_000_000,
+            native_fee_or_rebate: 30,
+            ..fill(1, OrderSide::Borrow, true, &borrower)
+        };
+        let taker = EventOutput::Fill {
+            native_qty_paid: 2_000_000,
+            native_qty_received: 99_960,
+            native_fee_or_rebate: 40,
+            ..fill(2, OrderSide::Lend, false, &lender)
+        };
+        let events = vec![maker, taker];
+
+        let fills = fills_from_events(&events, &borrower, 1_000_000, 10_000, 100);
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].size, 2);
+        assert_eq!(fills[0].rate, 5);
+        assert_eq!(fills[0].fee, 30);
+
+        let fills = fills_from_events(&events, &lender, 1_000_000, 10_000, 100);
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].rate, 5);
+    }
+
+    #[test]
+    fn test_fill_history_merge_ok() {
+        let owner = Pubkey::new_unique();
+        let events = vec![
+            fill(3, OrderSide::Lend, false, &owner),
+            fill(4, OrderSide::Lend, false, &owner),
+        ];
+        let fills = fills_from_events(&events, &owner, 1, 1, 100);
+
+        let mut history = FillHistory::default();
+        assert_eq!(history.merge(fills.clone()), 2);
+        assert_eq!(history.merge(fills), 0);
+        assert_eq!(history.fills.len(), 2);
+
+        let csv = history.to_csv();
+        assert_eq!(csv.lines().count(), 3);
+        assert_eq!(csv.lines().nth(1).unwrap().starts_with("100,lend,"), true);
+    }
+}
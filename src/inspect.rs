@@ -0,0 +1,372 @@
+use std::{collections::BTreeMap, str::FromStr};
+
+use anyhow::{format_err, Result};
+use serum_dex::{
+    instruction::{MarketInstruction, NewOrderInstructionV3},
+    matching::Side,
+};
+use solana_client::{rpc_client::RpcClient, rpc_config::RpcTransactionConfig};
+use solana_sdk::{
+    commitment_config::CommitmentConfig, message::VersionedMessage, pubkey::Pubkey,
+    signature::Signature,
+};
+use solana_transaction_status::{UiTransactionEncoding, UiTransactionTokenBalance};
+
+use crate::{InstructionOutput, TokenBalanceOutput, TransactionOutput};
+
+/// Fetches a confirmed transaction and decodes its Serum instructions and token balance changes.
+/// Token accounts found in `labels`, or owned by an account found there, are named after it.
+pub fn inspect_transaction(
+    client: &RpcClient,
+    dex_program_id: &Pubkey,
+    signature: &Signature,
+    labels: &BTreeMap<Pubkey, String>,
+) -> Result<TransactionOutput> {
+    let confirmed = client.get_transaction_with_config(
+        signature,
+        RpcTransactionConfig {
+            encoding: Some(UiTransactionEncoding::Base64),
+            commitment: Some(CommitmentConfig::confirmed()),
+            max_supported_transaction_version: Some(0),
+        },
+    )?;
+
+    let transaction = confirmed
+        .transaction
+        .transaction
+        .decode()
+        .ok_or_else(|| format_err!("unable to decode transaction {}", signature))?;
+    let meta = confirmed
+        .transaction
+        .meta
+        .ok_or_else(|| format_err!("transaction {} has no status", signature))?;
+
+    // v0 transactions index the accounts loaded from address lookup tables after their
+    // static keys, writable ones first
+    let mut account_keys = transaction.message.static_account_keys().to_vec();
+    if let VersionedMessage::V0(message) = &transaction.message {
+        if !message.address_table_lookups.is_empty() {
+            let loaded = meta.loaded_addresses.as_ref().ok_or_else(|| {
+                format_err!(
+                    "transaction {} doesn't list the addresses loaded from its lookup tables",
+                    signature
+                )
+            })?;
+            for address in loaded.writable.iter().chain(&loaded.readonly) {
+                account_keys.push(Pubkey::from_str(address)?);
+            }
+        }
+    }
+
+    let account = |index: u8| {
+        account_keys.get(index as usize).copied().ok_or_else(|| {
+            format_err!(
+                "transaction {} refers to a missing account {}",
+                signature,
+                index
+            )
+        })
+    };
+
+    let instructions = transaction
+        .message
+        .instructions()
+        .iter()
+        .enumerate()
+        .map(|(index, instruction)| {
+            let program_id = account(instruction.program_id_index)?;
+            let accounts = instruction
+                .accounts
+                .iter()
+                .map(|index| account(*index))
+                .collect::<Result<Vec<Pubkey>>>()?;
+
+            Ok(InstructionOutput {
+                index,
+                ..decode_instruction(dex_program_id, &program_id, &accounts, &instruction.data)
+            })
+        })
+        .collect::<Result<Vec<InstructionOutput>>>()?;
+
+    let token_balances = token_balance_changes(
+        &account_keys,
+        meta.pre_token_balances.as_deref().unwrap_or_default(),
+        meta.post_token_balances.as_deref().unwrap_or_default(),
+        labels,
+    );
+
+    Ok(TransactionOutput {
+        signature: signature.to_string(),
+        slot: confirmed.slot,
+        block_time: confirmed.block_time,
+        status: match meta.err {
+            Some(err) => format!("failed: {}", err),
+            None => String::from("success"),
+        },
+        fee: meta.fee,
+        instructions,
+        token_balances,
+        logs: meta.log_messages.unwrap_or_default(),
+    })
+}
+
+/// Decodes a single instruction, naming the instructions of other programs only.
+pub fn decode_instruction(
+    dex_program_id: &Pubkey,
+    program_id: &Pubkey,
+    accounts: &[Pubkey],
+    data: &[u8],
+) -> InstructionOutput {
+    let mut output = InstructionOutput {
+        index: 0,
+        program_id: program_id.to_string(),
+        name: program_name(program_id),
+        fields: Vec::new(),
+    };
+
+    if program_id != dex_program_id {
+        return output;
+    }
+
+    let instruction = match MarketInstruction::unpack(data) {
+        Some(instruction) => instruction,
+        None => {
+            output.name = String::from("unknown serum instruction");
+            return output;
+        }
+    };
+
+    let account = |index: usize| {
+        accounts
+            .get(index)
+            .map(|account| account.to_string())
+            .unwrap_or_else(|| String::from("missing"))
+    };
+
+    let mut fields: Vec<(&str, String)> = Vec::new();
+    let name = match instruction {
+        MarketInstruction::InitializeMarket(init) => {
+            fields.push(("market", account(0)));
+            fields.push(("coin mint", account(7)));
+            fields.push(("pc mint", account(8)));
+            fields.push(("coin lot size", init.coin_lot_size.to_string()));
+            fields.push(("pc lot size", init.pc_lot_size.to_string()));
+            fields.push(("fee rate bps", init.fee_rate_bps.to_string()));
+            fields.push(("pc dust threshold", init.pc_dust_threshold.to_string()));
+            "InitializeMarket"
+        }
+        MarketInstruction::NewOrderV3(new_order) => {
+            fields.push(("open orders", account(1)));
+            fields.push(("payer", account(6)));
+            new_order_fields(&mut fields, &new_order);
+            "NewOrderV3"
+        }
+        MarketInstruction::CancelOrderV2(cancel) => {
+            fields.push(("open orders", account(3)));
+            fields.push(("side", side_name(cancel.side).to_string()));
+            fields.push(("order id", cancel.order_id.to_string()));
+            "CancelOrderV2"
+        }
+        MarketInstruction::CancelOrderByClientIdV2(client_order_id) => {
+            fields.push(("open orders", account(3)));
+            fields.push(("client order id", client_order_id.to_string()));
+            "CancelOrderByClientIdV2"
+        }
+        MarketInstruction::CancelOrdersByClientIds(client_order_ids) => {
+            let client_order_ids: Vec<String> = client_order_ids
+                .iter()
+                .filter(|client_order_id| **client_order_id != 0)
+                .map(|client_order_id| client_order_id.to_string())
+                .collect();
+
+            fields.push(("open orders", account(3)));
+            fields.push(("client order ids", client_order_ids.join(", ")));
+            "CancelOrdersByClientIds"
+        }
+        MarketInstruction::SettleFunds => {
+            fields.push(("open orders", account(1)));
+            fields.push(("owner", account(2)));
+            fields.push(("coin wallet", account(5)));
+            fields.push(("pc wallet", account(6)));
+            "SettleFunds"
+        }
+        MarketInstruction::ConsumeEvents(limit) => {
+            fields.push(("limit", limit.to_string()));
+            "ConsumeEvents"
+        }
+        MarketInstruction::ConsumeEventsPermissioned(limit) => {
+            fields.push(("limit", limit.to_string()));
+            "ConsumeEventsPermissioned"
+        }
+        MarketInstruction::InitOpenOrders => {
+            fields.push(("open orders", account(0)));
+            fields.push(("owner", account(1)));
+            "InitOpenOrders"
+        }
+        MarketInstruction::CloseOpenOrders => {
+            fields.push(("open orders", account(0)));
+            fields.push(("owner", account(1)));
+            fields.push(("destination", account(2)));
+            "CloseOpenOrders"
+        }
+        other => {
+            output.name = format!("{:?}", other)
+                .split(|c: char| !c.is_alphanumeric())
+                .next()
+                .unwrap_or_default()
+                .to_string();
+            return output;
+        }
+    };
+
+    output.name = name.to_string();
+    output.fields = fields
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect();
+
+    output
+}
+
+fn new_order_fields(fields: &mut Vec<(&str, String)>, new_order: &NewOrderInstructionV3) {
+    fields.push(("side", side_name(new_order.side).to_string()));
+    fields.push(("rate", new_order.limit_price.to_string()));
+    fields.push(("max coin qty", new_order.max_coin_qty.to_string()));
+    fields.push((
+        "max native pc qty",
+        new_order.max_native_pc_qty_including_fees.to_string(),
+    ));
+    fields.push(("order type", format!("{:?}", new_order.order_type)));
+    fields.push((
+        "self trade behavior",
+        format!("{:?}", new_order.self_trade_behavior),
+    ));
+    fields.push(("client order id", new_order.client_order_id.to_string()));
+    fields.push(("limit", new_order.limit.to_string()));
+    fields.push(("max ts", new_order.max_ts.to_string()));
+}
+
+fn side_name(side: Side) -> &'static str {
+    match side {
+        Side::Ask => "lend",
+        Side::Bid => "borrow",
+    }
+}
+
+fn program_name(program_id: &Pubkey) -> String {
+    let name = if *program_id == solana_sdk::system_program::id() {
+        "system"
+    } else if *program_id == spl_token::id() {
+        "token"
+    } else if *program_id == spl_associated_token_account::id() {
+        "associated token account"
+    } else {
+        "unknown"
+    };
+
+    name.to_string()
+}
+
+/// Pairs the token balances before and after the transaction by account, skipping unchanged ones.
+fn token_balance_changes(
+    account_keys: &[Pubkey],
+    pre: &[UiTransactionTokenBalance],
+    post: &[UiTransactionTokenBalance],
+    labels: &BTreeMap<Pubkey, String>,
+) -> Vec<TokenBalanceOutput> {
+    let amount = |balance: &UiTransactionTokenBalance| {
+        balance.ui_token_amount.amount.parse::<u64>().unwrap_or(0)
+    };
+
+    let mut balances: BTreeMap<u8, (Option<&UiTransactionTokenBalance>, u64, u64)> =
+        BTreeMap::new();
+    for balance in pre {
+        let entry = balances.entry(balance.account_index).or_default();
+        entry.0 = Some(balance);
+        entry.1 = amount(balance);
+    }
+    for balance in post {
+        let entry = balances.entry(balance.account_index).or_default();
+        entry.0 = Some(balance);
+        entry.2 = amount(balance);
+    }
+
+    balances
+        .into_iter()
+        .filter(|(_, (_, pre, post))| pre != post)
+        .filter_map(|(index, (balance, pre, post))| {
+            let account = account_keys.get(index as usize)?;
+            let balance = balance?;
+            let owner = balance
+                .owner
+                .as_ref()
+                .and_then(|owner| owner.parse::<Pubkey>().ok());
+
+            let label = labels.get(account).cloned().or_else(|| {
+                owner
+                    .and_then(|owner| labels.get(&owner))
+                    .map(|label| label.clone() + " token account")
+            });
+
+            Some(TokenBalanceOutput {
+                account: account.to_string(),
+                mint: balance.mint.clone(),
+                label,
+                pre,
+                post,
+                delta: post as i128 - pre as i128,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serum_dex::instruction::SelfTradeBehavior;
+    use serum_dex::matching::OrderType;
+    use std::num::NonZeroU64;
+
+    #[test]
+    fn test_decode_new_order_ok() {
+        let dex_program_id = Pubkey::new_unique();
+        let accounts: Vec<Pubkey> = (0..12).map(|_| Pubkey::new_unique()).collect();
+        let data = MarketInstruction::NewOrderV3(NewOrderInstructionV3 {
+            side: Side::Ask,
+            limit_price: NonZeroU64::new(5).unwrap(),
+            max_coin_qty: NonZeroU64::new(100).unwrap(),
+            max_native_pc_qty_including_fees: NonZeroU64::new(500).unwrap(),
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            order_type: OrderType::PostOnly,
+            client_order_id: 42,
+            limit: 65535,
+            max_ts: i64::MAX,
+        })
+        .pack();
+
+        let output = decode_instruction(&dex_program_id, &dex_program_id, &accounts, &data);
+        assert_eq!(output.name, "NewOrderV3");
+
+        let field = |key: &str| {
+            output
+                .fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value.clone())
+        };
+        assert_eq!(field("open orders"), Some(accounts[1].to_string()));
+        assert_eq!(field("side"), Some(String::from("lend")));
+        assert_eq!(field("rate"), Some(String::from("5")));
+        assert_eq!(field("client order id"), Some(String::from("42")));
+    }
+
+    #[test]
+    fn test_decode_other_program_ok() {
+        let dex_program_id = Pubkey::new_unique();
+        let output =
+            decode_instruction(&dex_program_id, &spl_token::id(), &[], &[0xff, 0xff, 0xff]);
+
+        assert_eq!(output.name, "token");
+        assert_eq!(output.fields.is_empty(), true);
+    }
+}
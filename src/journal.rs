@@ -0,0 +1,265 @@
+use std::{collections::HashSet, path::Path};
+
+use anyhow::{format_err, Result};
+use serde::{Deserialize, Serialize};
+use serum_dex::matching::Side;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{read_file, unix_timestamp, write_file, ORDER_JOURNAL};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderSide {
+    Lend,
+    Borrow,
+}
+
+impl From<Side> for OrderSide {
+    fn from(side: Side) -> Self {
+        match side {
+            Side::Ask => OrderSide::Lend,
+            Side::Bid => OrderSide::Borrow,
+        }
+    }
+}
+
+impl From<OrderSide> for Side {
+    fn from(side: OrderSide) -> Self {
+        match side {
+            OrderSide::Lend => Side::Ask,
+            OrderSide::Borrow => Side::Bid,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct JournalEntry {
+    pub client_order_id: u64,
+    pub side: OrderSide,
+    pub size: u64,
+    pub rate: u64,
+    pub signature: String,
+    pub timestamp: i64,
+    /// Unix timestamp after which the order should be cancelled by `sweep-expired`.
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+    #[serde(default)]
+    pub cancelled: bool,
+    /// Signature of the transaction which cancelled the order, if it was still in the book.
+    #[serde(default)]
+    pub cancel_signature: Option<String>,
+}
+
+/// Local record of the orders placed by a wallet, keyed by client order id.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct OrderJournal {
+    pub last_client_order_id: u64,
+    pub entries: Vec<JournalEntry>,
+    /// Ids handed out since the journal was loaded, including the ones of orders which
+    /// aren't recorded yet, like the other orders of a batch.
+    #[serde(skip)]
+    reserved: HashSet<u64>,
+}
+
+impl OrderJournal {
+    pub fn file_name(owner: &Pubkey) -> String {
+        owner.to_string() + "_" + ORDER_JOURNAL
+    }
+
+    /// Loads the journal of the given wallet, or an empty one if nothing was recorded yet.
+    pub fn load(dir_name: &str, owner: &Pubkey) -> Result<Self> {
+        let path = Path::new(dir_name).join(Self::file_name(owner));
+        if !path.exists() {
+            return Ok(OrderJournal::default());
+        }
+
+        let content = read_file(path.to_str().unwrap()).map_err(|err| format_err!(err))?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save(&self, dir_name: &str, owner: &Pubkey) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        write_file(dir_name, &Self::file_name(owner), &content).map_err(|err| format_err!(err))
+    }
+
+    /// Returns a new client order id which is greater than any id generated before and
+    /// isn't taken by an order. Ids are seeded from the current time, so they stay unique
+    /// even if the journal is lost.
+    pub fn next_client_order_id(&mut self) -> Result<u64> {
+        let seed = unix_timestamp()? as u64 * 1_000;
+        let exhausted = || format_err!("client order ids are exhausted");
+
+        let mut client_order_id = std::cmp::max(
+            self.last_client_order_id
+                .checked_add(1)
+                .ok_or_else(exhausted)?,
+            seed,
+        );
+        while self.is_taken(client_order_id) {
+            client_order_id = client_order_id.checked_add(1).ok_or_else(exhausted)?;
+        }
+
+        self.last_client_order_id = client_order_id;
+        self.reserved.insert(client_order_id);
+        Ok(client_order_id)
+    }
+
+    /// Reserves an id chosen by the user, rejecting ids which are already recorded or
+    /// were handed out since the journal was loaded. The generated ids don't move past
+    /// it, they skip it once it's taken.
+    pub fn use_client_order_id(&mut self, client_order_id: u64) -> Result<u64> {
+        if client_order_id == 0 {
+            return Err(format_err!("client order id must be greater than zero"));
+        }
+
+        if self.is_taken(client_order_id) {
+            return Err(format_err!(
+                "client order id {} is already used by another order",
+                client_order_id
+            ));
+        }
+
+        self.reserved.insert(client_order_id);
+        Ok(client_order_id)
+    }
+
+    fn is_taken(&self, client_order_id: u64) -> bool {
+        self.find(client_order_id).is_some() || self.reserved.contains(&client_order_id)
+    }
+
+    pub fn record(&mut self, entry: JournalEntry) {
+        self.entries.push(entry);
+    }
+
+    /// Client order ids of the orders whose good-till-time has passed and which
+    /// weren't cancelled yet.
+    pub fn expired_client_order_ids(&self, now: i64) -> Vec<u64> {
+        self.entries
+            .iter()
+            .filter(|entry| !entry.cancelled)
+            .filter(|entry| matches!(entry.expires_at, Some(expires_at) if expires_at <= now))
+            .map(|entry| entry.client_order_id)
+            .collect()
+    }
+
+    pub fn mark_cancelled(&mut self, client_order_ids: &[u64], signature: Option<&str>) {
+        for entry in self.entries.iter_mut() {
+            if client_order_ids.contains(&entry.client_order_id) {
+                entry.cancelled = true;
+                entry.cancel_signature = signature.map(str::to_string);
+            }
+        }
+    }
+
+    pub fn find(&self, client_order_id: u64) -> Option<&JournalEntry> {
+        self.entries
+            .iter()
+            .find(|entry| entry.client_order_id == client_order_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remove_dir_and_files;
+
+    fn entry(client_order_id: u64) -> JournalEntry {
+        JournalEntry {
+            client_order_id,
+            side: OrderSide::Lend,
+            size: 10,
+            rate: 5,
+            signature: "signature".to_string(),
+            timestamp: 0,
+            expires_at: None,
+            cancelled: false,
+            cancel_signature: None,
+        }
+    }
+
+    #[test]
+    fn test_next_client_order_id_is_increasing() {
+        let mut journal = OrderJournal::default();
+        let first = journal.next_client_order_id().unwrap();
+        let second = journal.next_client_order_id().unwrap();
+        assert_eq!(second > first, true);
+
+        journal.last_client_order_id = std::u64::MAX - 1;
+        let third = journal.next_client_order_id().unwrap();
+        assert_eq!(third, std::u64::MAX);
+    }
+
+    #[test]
+    fn test_use_client_order_id_failed() {
+        let mut journal = OrderJournal::default();
+        assert_eq!(journal.use_client_order_id(0).is_err(), true);
+
+        journal.record(entry(42));
+        assert_eq!(journal.use_client_order_id(42).is_err(), true);
+        assert_eq!(journal.use_client_order_id(43).unwrap(), 43);
+        assert_eq!(journal.last_client_order_id, 0);
+
+        // an id reserved for an order which isn't recorded yet can't be used twice
+        assert_eq!(journal.use_client_order_id(43).is_err(), true);
+        let next = journal.next_client_order_id().unwrap();
+        assert_eq!(journal.use_client_order_id(next).is_err(), true);
+    }
+
+    #[test]
+    fn test_next_client_order_id_skips_used_ids() {
+        let mut journal = OrderJournal::default();
+        assert_eq!(journal.use_client_order_id(std::u64::MAX).is_ok(), true);
+        assert_eq!(journal.next_client_order_id().is_ok(), true);
+
+        journal.last_client_order_id = std::u64::MAX - 4;
+        journal.record(entry(std::u64::MAX - 3));
+        journal.use_client_order_id(std::u64::MAX - 2).unwrap();
+        assert_eq!(journal.next_client_order_id().unwrap(), std::u64::MAX - 1);
+        assert_eq!(journal.next_client_order_id().is_err(), true);
+    }
+
+    #[test]
+    fn test_expired_client_order_ids_ok() {
+        let mut journal = OrderJournal::default();
+        journal.record(entry(1));
+        journal.record(JournalEntry {
+            expires_at: Some(100),
+            ..entry(2)
+        });
+        journal.record(JournalEntry {
+            expires_at: Some(200),
+            ..entry(3)
+        });
+
+        assert_eq!(journal.expired_client_order_ids(150), vec![2]);
+        assert_eq!(journal.expired_client_order_ids(200), vec![2, 3]);
+
+        journal.mark_cancelled(&[2], Some("cancel"));
+        assert_eq!(journal.expired_client_order_ids(200), vec![3]);
+        assert_eq!(journal.find(2).unwrap().cancel_signature, Some("cancel".to_string()));
+    }
+
+    #[test]
+    fn test_journal_save_and_load_ok() {
+        let dir_name = "test_journal_save_and_load_ok";
+        let owner = Pubkey::new_unique();
+
+        let journal = OrderJournal::load(dir_name, &owner).unwrap();
+        assert_eq!(journal, OrderJournal::default());
+
+        let mut journal = OrderJournal::default();
+        journal.next_client_order_id().unwrap();
+        journal.record(entry(journal.last_client_order_id));
+
+        let result = journal.save(dir_name, &owner);
+        assert_eq!(result.is_ok(), true);
+
+        // reserved ids aren't saved, only the recorded orders
+        let loaded = OrderJournal::load(dir_name, &owner).unwrap();
+        assert_eq!(loaded.last_client_order_id, journal.last_client_order_id);
+        assert_eq!(loaded.entries, journal.entries);
+
+        let result = remove_dir_and_files(dir_name);
+        assert_eq!(result.is_ok(), true);
+    }
+}
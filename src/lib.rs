@@ -1,22 +1,47 @@
+pub mod batch;
+pub mod cache;
+pub mod decode;
 pub mod dex;
+pub mod fills;
+pub mod inspect;
+pub mod journal;
 pub mod market;
 pub mod order;
+pub mod output;
+pub mod session;
+pub mod shell;
+pub mod terminal;
 pub mod utils;
 
+pub use batch::*;
+pub use cache::*;
+pub use decode::*;
 pub use dex::*;
+pub use fills::*;
+pub use inspect::*;
+pub use journal::*;
 pub use market::*;
 pub use order::*;
+pub use output::*;
+pub use session::*;
+pub use shell::*;
+pub use terminal::*;
 pub use utils::*;
 
 use solana_sdk::pubkey::Pubkey;
 
 pub const CONFIG_DIR: &str = "configs";
+pub const BACKUP_DIR: &str = "backups";
 
 pub const COIN_MINT: &str = "coin_mint.json";
 pub const PC_MINT: &str = "pc_mint.json";
 
 pub const MARKET_PUBKEY: &str = "market_pubkey.json";
+pub const MARKET_CACHE: &str = "market_cache.json";
 pub const OPEN_ORDER: &str = "open_order_pubkey.json";
+pub const ORDER_JOURNAL: &str = "order_journal.json";
+pub const FILL_HISTORY: &str = "fill_history.json";
+pub const SHELL_HISTORY: &str = "shell_history.txt";
 
 pub const URL: &str = "url.json";
 pub const PROGRAM_ID: &str = "program_id.json";
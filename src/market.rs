@@ -1,13 +1,39 @@
+use std::{convert::identity, str::FromStr};
+
 use bytemuck::bytes_of;
 use debug_print::debug_println;
+use safe_transmute::transmute_one_to_bytes;
+use serum_dex::state::MarketState;
 use solana_client::rpc_client::RpcClient;
 use solana_sdk::{
-    message::Message, program_error::ProgramError, pubkey::Pubkey, signature::Keypair,
-    signer::Signer, system_instruction, transaction::Transaction,
+    message::Message,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+    system_instruction,
+    transaction::Transaction,
 };
-
-use crate::{MarketPubkeys, CONFIG_DIR, COIN_MINT, PC_MINT, WALLET, PROGRAM_ID, URL, MARKET_PUBKEY, OPEN_ORDER, read_file};
-use anyhow::Result;
+use spl_associated_token_account::get_associated_token_address;
+
+use crate::{
+    decode_market_state, get_mint_decimals, get_token_balance, load_market_cache,
+    load_market_snapshot, market_keys_from_state, read_file, read_keypair_file,
+    read_open_order_pubkey, render, summarize_open_orders, write_file, InfoOutput, MarketCache,
+    MarketOutput, MarketPubkeys, OpenOrdersOutput, OpenOrdersSummary, OutputFormat, WalletOutput,
+    COIN_MINT, CONFIG_DIR, MARKET_PUBKEY, OPEN_ORDER, PC_MINT, PROGRAM_ID, URL, WALLET,
+};
+use anyhow::{format_err, Result};
+
+/// Parameters of a market which are needed to trade on it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarketInfo {
+    pub coin_mint: Pubkey,
+    pub pc_mint: Pubkey,
+    pub coin_lot_size: u64,
+    pub pc_lot_size: u64,
+    pub fee_rate_bps: u64,
+}
 
 pub fn new(
     client: &RpcClient,
@@ -17,7 +43,7 @@ pub fn new(
     base_lot_size: u64,
     quote_lot_size: u64,
     dex_program_id: &Pubkey,
-) -> Result<MarketPubkeys> {
+) -> Result<(MarketPubkeys, Vec<Signature>)> {
 
     // Generating keypairs
     let market = Keypair::new();
@@ -75,6 +101,7 @@ pub fn new(
         "base vault transaction confirmed with signature: {:?}",
         signature
     );
+    let mut signatures = vec![signature];
 
     let quote_vault_account_ix = system_instruction::create_account(
         &payer.pubkey(),
@@ -105,6 +132,7 @@ pub fn new(
         "quote vault transaction confirmed with signature: {:?}",
         signature
     );
+    signatures.push(signature);
 
     debug_println!("\ncreating accounts and initializing market...");
     let data_len = 376 + 12;
@@ -193,8 +221,9 @@ pub fn new(
 
     let signature = client.send_and_confirm_transaction(&transaction)?;
     debug_println!("Market is initialized with signature: {:?}\n", signature);
+    signatures.push(signature);
 
-    Ok(MarketPubkeys {
+    let market_keys = MarketPubkeys {
         market: Box::new(market.pubkey()),
         req_q: Box::new(request_queue.pubkey()),
         event_q: Box::new(event_queue.pubkey()),
@@ -203,13 +232,80 @@ pub fn new(
         coin_vault: Box::new(base_vault.pubkey()),
         pc_vault: Box::new(quote_vault.pubkey()),
         vault_signer_key: Box::new(vault_owner),
-    })
+    };
+
+    Ok((market_keys, signatures))
+}
+
+impl MarketInfo {
+    pub fn from_state(market_state: &MarketState) -> Self {
+        MarketInfo {
+            coin_mint: Pubkey::new(transmute_one_to_bytes(&identity(market_state.coin_mint))),
+            pc_mint: Pubkey::new(transmute_one_to_bytes(&identity(market_state.pc_mint))),
+            coin_lot_size: market_state.coin_lot_size,
+            pc_lot_size: market_state.pc_lot_size,
+            fee_rate_bps: market_state.fee_rate_bps,
+        }
+    }
+}
+
+/// Fetches and decodes the market account once, verifying that it belongs to the DEX
+/// program, and returns its keys and parameters.
+pub fn load_market(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    market: &Pubkey,
+) -> Result<(MarketPubkeys, MarketInfo)> {
+    let account = client.get_account(market)?;
+    if account.owner != *program_id {
+        return Err(format_err!(
+            "market {} is owned by {}, not by the DEX program {}",
+            market,
+            account.owner,
+            program_id
+        ));
+    }
+
+    let market_state = decode_market_state(&account.data)?;
+    let keys = market_keys_from_state(program_id, market, &market_state)?;
+
+    Ok((keys, MarketInfo::from_state(&market_state)))
+}
+
+/// Reads the parameters of an existing market, verifying that it belongs to the DEX program.
+pub fn load_market_info(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    market: &Pubkey,
+) -> Result<MarketInfo> {
+    Ok(load_market(client, program_id, market)?.1)
 }
 
-pub fn info() {
+/// Records the market and its mints in the config, its other parameters are kept in the
+/// market cache only.
+pub fn save_market_info(market: &Pubkey, info: &MarketInfo) -> Result<(), String> {
+    write_file(CONFIG_DIR, MARKET_PUBKEY, market.to_string().as_str())?;
+    write_file(CONFIG_DIR, COIN_MINT, info.coin_mint.to_string().as_str())?;
+    write_file(CONFIG_DIR, PC_MINT, info.pc_mint.to_string().as_str())?;
+
+    Ok(())
+}
+
+/// Prints the config files together with the on-chain state of the market.
+pub fn info(format: OutputFormat) {
+    let output = load_info();
+
+    if let Err(err) = render(format, &output) {
+        debug_println!("{:?}", err);
+    }
+}
+
+/// Collects the config files and the on-chain state of the market for `info`.
+pub fn load_info() -> InfoOutput {
+    let mut output = InfoOutput::default();
+
     if !std::path::Path::new(CONFIG_DIR).exists() {
-        println!("There is no information!");
-        return;
+        return output;
     }
 
     for entry in std::fs::read_dir(CONFIG_DIR).unwrap() {
@@ -218,26 +314,157 @@ pub fn info() {
 
         if path.is_file() {
             let file_name = path.file_name().unwrap().to_str().unwrap();
-            let full_path = CONFIG_DIR.to_string() + "/" + file_name;
-            let result = read_file(full_path.as_str());
-
-            if file_name == COIN_MINT {
-                println!("Coint mint: {}", result.unwrap());
-            } else if file_name == PC_MINT {
-                println!("Pc mint: {}", result.unwrap());
-            } else if file_name.starts_with(WALLET) {
-                println!("Wallet: {}", result.unwrap());
-            } else if file_name == URL {
-                println!("URL: {}", result.unwrap());
-            } else if file_name == PROGRAM_ID {
-                println!("Program ID: {}", result.unwrap());
-            } else if file_name == MARKET_PUBKEY {
-                println!("Market pubkey: {}", result.unwrap());
-            } else if file_name.starts_with(OPEN_ORDER) {
-                println!("Open order pubkey: {}", result.unwrap());
+            let is_config = [COIN_MINT, PC_MINT, URL, PROGRAM_ID, MARKET_PUBKEY]
+                .contains(&file_name)
+                || file_name.starts_with(WALLET)
+                || file_name.ends_with(OPEN_ORDER);
+
+            if !is_config {
+                continue;
+            }
+
+            if let Ok(content) = read_config(file_name) {
+                let key = file_name.trim_end_matches(".json").to_string();
+                output.config.insert(key, content);
             }
         }
     }
+
+    if output.config.is_empty() {
+        return output;
+    }
+
+    if let Err(err) = load_on_chain_info(&mut output) {
+        output.error = Some(err.to_string());
+    }
+
+    output
+}
+
+/// Decoded on-chain state of a market.
+#[derive(Debug)]
+pub struct MarketOverview {
+    pub keys: MarketPubkeys,
+    pub info: MarketInfo,
+    pub coin_decimals: u8,
+    pub pc_decimals: u8,
+    pub coin_vault_balance: u64,
+    pub pc_vault_balance: u64,
+    pub coin_deposits_total: u64,
+    pub pc_deposits_total: u64,
+    pub coin_fees_accrued: u64,
+    pub pc_fees_accrued: u64,
+    pub referrer_rebates_accrued: u64,
+    pub pc_dust_threshold: u64,
+    /// Summary of the OpenOrders account, if one was requested.
+    pub open_orders: Option<OpenOrdersSummary>,
+}
+
+/// Loads the market state, its vault balances and optionally the summary of an OpenOrders
+/// account from a single snapshot. The accounts to fetch are taken from the market cache,
+/// which the snapshot checks against the market account.
+pub fn load_market_overview(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    market: &Pubkey,
+    orders: Option<&Pubkey>,
+) -> Result<MarketOverview> {
+    let cached_keys = load_market_cache(client, program_id, market)?.market_keys()?;
+    let snapshot = load_market_snapshot(client, &cached_keys, orders)?;
+
+    if snapshot.market.owner != *program_id {
+        return Err(format_err!(
+            "market {} is owned by {}, not by the DEX program {}",
+            market,
+            snapshot.market.owner,
+            program_id
+        ));
+    }
+
+    let market_state = snapshot.market_state()?;
+    let keys = market_keys_from_state(program_id, market, &market_state)?;
+    let info = MarketInfo::from_state(&market_state);
+
+    let open_orders = match (orders, snapshot.open_orders()?) {
+        (Some(orders), Some(open_orders)) => Some(summarize_open_orders(
+            orders,
+            &open_orders,
+            &snapshot.orderbook(None)?,
+        )),
+        _ => None,
+    };
+
+    Ok(MarketOverview {
+        coin_decimals: get_mint_decimals(client, &info.coin_mint)?,
+        pc_decimals: get_mint_decimals(client, &info.pc_mint)?,
+        coin_vault_balance: snapshot.coin_vault_balance()?,
+        pc_vault_balance: snapshot.pc_vault_balance()?,
+        coin_deposits_total: market_state.coin_deposits_total,
+        pc_deposits_total: market_state.pc_deposits_total,
+        coin_fees_accrued: market_state.coin_fees_accrued,
+        pc_fees_accrued: market_state.pc_fees_accrued,
+        referrer_rebates_accrued: market_state.referrer_rebates_accrued,
+        pc_dust_threshold: market_state.pc_dust_threshold,
+        keys,
+        info,
+        open_orders,
+    })
+}
+
+fn read_config(file_name: &str) -> Result<String> {
+    let path = CONFIG_DIR.to_string() + "/" + file_name;
+    read_file(path.as_str()).map_err(|err| format_err!(err))
+}
+
+fn load_on_chain_info(output: &mut InfoOutput) -> Result<()> {
+    let client = RpcClient::new(read_config(URL)?);
+    let program_id = Pubkey::from_str(&read_config(PROGRAM_ID)?)?;
+    let market = Pubkey::from_str(&read_config(MARKET_PUBKEY)?)?;
+
+    let wallet = read_config(WALLET)
+        .and_then(|path| read_keypair_file(&path))
+        .map(|wallet| wallet.pubkey())
+        .ok();
+    let orders = wallet.and_then(|wallet| read_open_order_pubkey(&wallet).ok());
+
+    let overview = load_market_overview(&client, &program_id, &market, orders.as_ref())?;
+    output.market = Some(MarketOutput::from(&overview));
+    output.open_orders = overview.open_orders.as_ref().map(OpenOrdersOutput::from);
+
+    // trading commands rely on the cache, so report when it no longer matches the market
+    if let Ok(Some(cache)) = MarketCache::load(CONFIG_DIR) {
+        let current = MarketCache::new(
+            &program_id,
+            &overview.keys,
+            &overview.info,
+            overview.coin_decimals,
+            overview.pc_decimals,
+        );
+        let stale = cache.mismatches(&current);
+        if !stale.is_empty() {
+            output.error = Some(format!(
+                "market cache doesn't match the market in {}, run with --refresh",
+                stale.join(", ")
+            ));
+        }
+    }
+
+    let wallet = match wallet {
+        Some(wallet) => wallet,
+        None => return Ok(()),
+    };
+
+    let coin_wallet = get_associated_token_address(&wallet, &overview.info.coin_mint);
+    let pc_wallet = get_associated_token_address(&wallet, &overview.info.pc_mint);
+
+    output.wallet = Some(WalletOutput {
+        pubkey: wallet.to_string(),
+        lamports: client.get_balance(&wallet)?,
+        coin_balance: get_token_balance(&client, &coin_wallet)?,
+        pc_balance: get_token_balance(&client, &pc_wallet)?,
+    });
+
+    Ok(())
 }
 
 fn gen_vault_signer_key(
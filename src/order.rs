@@ -1,28 +1,286 @@
-use std::{cell::RefCell, mem::size_of, ops::Deref, rc::Rc};
+use std::{mem::size_of, num::NonZeroU64, str::FromStr, sync::mpsc, thread, time::Duration};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
 
-use debug_print::debug_println;
 use serum_dex::{
-    critbit::SlabView,
+    critbit::LeafNode,
+    fees::FeeTier,
     instruction::{
         cancel_orders_by_client_order_ids as cancel_order_by_client_order_ids_ix,
-        MarketInstruction, NewOrderInstructionV3,
+        settle_funds as settle_funds_ix, MarketInstruction, NewOrderInstructionV3,
+        SelfTradeBehavior,
     },
-    state::Market,
+    matching::{OrderType, Side},
+    state::{AccountFlag, Event, OpenOrders},
 };
 
-use solana_client::rpc_client::RpcClient;
+use solana_client::{pubsub_client::PubsubClient, rpc_client::RpcClient};
 use solana_sdk::{
-    account::Account,
-    account_info::AccountInfo,
     instruction::{AccountMeta, Instruction},
     pubkey::Pubkey,
-    signature::Keypair,
+    signature::{Keypair, Signature},
     signer::Signer,
     transaction::Transaction,
 };
 
-use crate::{get_keys_for_market, MarketPubkeys};
-use anyhow::Result;
+use crate::{
+    decode_slab, get_cached_keys_for_market, load_event_queue, load_market_cache,
+    load_market_snapshot, load_request_queue, render, unix_timestamp, EventOutput, EventsOutput,
+    FetchOutput, MarketPubkeys, MarketSnapshot, OrderSide, OutputFormat, QueueHeader,
+    RequestOutput, RequestsOutput,
+};
+use anyhow::{format_err, Result};
+
+/// Order type as it's written on the command line, in batch files, in the shell and in
+/// the output.
+#[derive(Serialize, Deserialize, ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum OrderKind {
+    #[default]
+    Limit,
+    Ioc,
+    PostOnly,
+}
+
+impl From<OrderKind> for OrderType {
+    fn from(kind: OrderKind) -> Self {
+        match kind {
+            OrderKind::Limit => OrderType::Limit,
+            OrderKind::Ioc => OrderType::ImmediateOrCancel,
+            OrderKind::PostOnly => OrderType::PostOnly,
+        }
+    }
+}
+
+impl From<OrderType> for OrderKind {
+    fn from(order_type: OrderType) -> Self {
+        match order_type {
+            OrderType::Limit => OrderKind::Limit,
+            OrderType::ImmediateOrCancel => OrderKind::Ioc,
+            OrderType::PostOnly => OrderKind::PostOnly,
+        }
+    }
+}
+
+impl OrderKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            OrderKind::Limit => "limit",
+            OrderKind::Ioc => "ioc",
+            OrderKind::PostOnly => "post-only",
+        }
+    }
+}
+
+impl FromStr for OrderKind {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        OrderKind::value_variants()
+            .iter()
+            .copied()
+            .find(|kind| kind.name() == name)
+            .ok_or_else(|| {
+                format_err!(
+                    "unknown order type {}, expected limit, ioc or post-only",
+                    name
+                )
+            })
+    }
+}
+
+/// Number of seconds a new order has to land on-chain before the DEX rejects it.
+pub const DEFAULT_ORDER_TTL_SECS: i64 = 20;
+
+/// Builds a `NewOrderInstructionV3` with sensible defaults and validates it
+/// against the market lot sizes and the wallet balance before it is sent.
+#[derive(Debug, Clone)]
+pub struct OrderBuilder {
+    side: Side,
+    limit_price: u64,
+    max_coin_qty: u64,
+    order_type: OrderType,
+    self_trade_behavior: SelfTradeBehavior,
+    client_order_id: u64,
+    limit: u16,
+    max_ts: Option<i64>,
+    coin_lot_size: u64,
+    pc_lot_size: u64,
+    fee_tier: FeeTier,
+    balance: Option<u64>,
+}
+
+impl OrderBuilder {
+    pub fn new(side: Side) -> Self {
+        OrderBuilder {
+            side,
+            limit_price: 0,
+            max_coin_qty: 0,
+            order_type: OrderType::Limit,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            client_order_id: 0,
+            limit: std::u16::MAX,
+            max_ts: None,
+            coin_lot_size: 1,
+            pc_lot_size: 1,
+            fee_tier: FeeTier::Base,
+            balance: None,
+        }
+    }
+
+    /// Limit price in pc lots per coin lot, i.e. the interest rate.
+    pub fn price(mut self, limit_price: u64) -> Self {
+        self.limit_price = limit_price;
+        self
+    }
+
+    /// Order size in coin lots.
+    pub fn size(mut self, max_coin_qty: u64) -> Self {
+        self.max_coin_qty = max_coin_qty;
+        self
+    }
+
+    pub fn order_type(mut self, order_type: OrderType) -> Self {
+        self.order_type = order_type;
+        self
+    }
+
+    pub fn self_trade_behavior(mut self, self_trade_behavior: SelfTradeBehavior) -> Self {
+        self.self_trade_behavior = self_trade_behavior;
+        self
+    }
+
+    pub fn client_order_id(mut self, client_order_id: u64) -> Self {
+        self.client_order_id = client_order_id;
+        self
+    }
+
+    /// Maximum number of matching iterations the DEX performs for this order.
+    pub fn limit(mut self, limit: u16) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Unix timestamp after which the DEX rejects the order. Defaults to
+    /// `DEFAULT_ORDER_TTL_SECS` from the moment `build` is called.
+    pub fn max_ts(mut self, max_ts: i64) -> Self {
+        self.max_ts = Some(max_ts);
+        self
+    }
+
+    pub fn lot_sizes(mut self, coin_lot_size: u64, pc_lot_size: u64) -> Self {
+        self.coin_lot_size = coin_lot_size;
+        self.pc_lot_size = pc_lot_size;
+        self
+    }
+
+    /// Fee tier used to reserve the taker fee of a bid. The DEX takes the tier from the
+    /// SRM or MSRM account passed along with the order, and the CLI never passes one, so
+    /// its orders always pay the `Base` fee. That's also the highest taker fee, which
+    /// makes the default a safe upper bound for the pc amount a bid locks. The market's
+    /// `fee_rate_bps` isn't used by the DEX to charge fees and doesn't change this.
+    pub fn fee_tier(mut self, fee_tier: FeeTier) -> Self {
+        self.fee_tier = fee_tier;
+        self
+    }
+
+    /// Native balance of the wallet the order is paid from: coin for asks, pc for bids.
+    pub fn balance(mut self, balance: u64) -> Self {
+        self.balance = Some(balance);
+        self
+    }
+
+    /// Native coin amount locked by an ask.
+    pub fn native_coin_qty(&self) -> Result<u64> {
+        self.max_coin_qty
+            .checked_mul(self.coin_lot_size)
+            .ok_or_else(|| {
+                format_err!(
+                    "order size {} overflows native coin amount",
+                    self.max_coin_qty
+                )
+            })
+    }
+
+    /// Native pc amount locked by a bid, including the taker fee of the fee tier.
+    pub fn max_native_pc_qty(&self) -> Result<u64> {
+        let native_pc_qty = self
+            .limit_price
+            .checked_mul(self.max_coin_qty)
+            .and_then(|qty| qty.checked_mul(self.pc_lot_size))
+            .ok_or_else(|| {
+                format_err!(
+                    "order of size {} at rate {} overflows native pc amount",
+                    self.max_coin_qty,
+                    self.limit_price
+                )
+            })?;
+
+        native_pc_qty
+            .checked_add(self.fee_tier.taker_fee(native_pc_qty))
+            .ok_or_else(|| format_err!("taker fee overflows native pc amount"))
+    }
+
+    pub fn build(self) -> Result<NewOrderInstructionV3> {
+        let limit_price = NonZeroU64::new(self.limit_price)
+            .ok_or_else(|| format_err!("order rate must be greater than zero"))?;
+        let max_coin_qty = NonZeroU64::new(self.max_coin_qty)
+            .ok_or_else(|| format_err!("order size must be greater than zero"))?;
+
+        if self.coin_lot_size == 0 || self.pc_lot_size == 0 {
+            return Err(format_err!("market lot sizes must be greater than zero"));
+        }
+
+        if self.limit == 0 {
+            return Err(format_err!("order matching limit must be greater than zero"));
+        }
+
+        let (required, max_native_pc_qty_including_fees) = match self.side {
+            Side::Ask => (self.native_coin_qty()?, std::u64::MAX),
+            Side::Bid => {
+                let max_native_pc_qty = self.max_native_pc_qty()?;
+                (max_native_pc_qty, max_native_pc_qty)
+            }
+        };
+
+        if let Some(balance) = self.balance {
+            if balance < required {
+                return Err(format_err!(
+                    "insufficient balance: order requires {} but wallet holds {}",
+                    required,
+                    balance
+                ));
+            }
+        }
+
+        let now = unix_timestamp()?;
+        let max_ts = match self.max_ts {
+            Some(max_ts) if max_ts <= now => {
+                return Err(format_err!(
+                    "order deadline {} has already passed, current time is {}",
+                    max_ts,
+                    now
+                ));
+            }
+            Some(max_ts) => max_ts,
+            None => now + DEFAULT_ORDER_TTL_SECS,
+        };
+
+        Ok(NewOrderInstructionV3 {
+            side: self.side,
+            limit_price,
+            max_coin_qty,
+            max_native_pc_qty_including_fees: NonZeroU64::new(max_native_pc_qty_including_fees)
+                .ok_or_else(|| format_err!("order doesn't lock any pc amount"))?,
+            order_type: self.order_type,
+            self_trade_behavior: self.self_trade_behavior,
+            client_order_id: self.client_order_id,
+            limit: self.limit,
+            max_ts,
+        })
+    }
+}
 
 pub fn place_order(
     client: &RpcClient,
@@ -32,7 +290,7 @@ pub fn place_order(
     state: &MarketPubkeys,
     orders: &mut Option<Pubkey>,
     new_order: NewOrderInstructionV3,
-) -> Result<()> {
+) -> Result<Signature> {
     let mut instructions = Vec::new();
     let mut signers = Vec::new();
 
@@ -57,138 +315,584 @@ pub fn place_order(
     };
 
     *orders = Some(orders_pubkey);
-    let _side = new_order.side;
 
+    instructions.push(new_order_instruction(
+        program_id,
+        &payer.pubkey(),
+        wallet,
+        state,
+        &orders_pubkey,
+        new_order,
+    ));
+    signers.push(payer);
+
+    let recent_hash = client.get_latest_blockhash()?;
+    let txn = Transaction::new_signed_with_payer(
+        &instructions,
+        Some(&payer.pubkey()),
+        &signers,
+        recent_hash,
+    );
+
+    let signature = client.send_and_confirm_transaction(&txn)?;
+
+    Ok(signature)
+}
+
+pub fn new_order_instruction(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    wallet: &Pubkey,
+    state: &MarketPubkeys,
+    orders: &Pubkey,
+    new_order: NewOrderInstructionV3,
+) -> Instruction {
     let data = MarketInstruction::NewOrderV3(new_order).pack();
 
-    let instruction = Instruction {
+    Instruction {
         program_id: *program_id,
         data,
         accounts: vec![
             AccountMeta::new(*state.market, false),
-            AccountMeta::new(orders_pubkey, false),
+            AccountMeta::new(*orders, false),
             AccountMeta::new(*state.req_q, false),
             AccountMeta::new(*state.event_q, false),
             AccountMeta::new(*state.bids, false),
             AccountMeta::new(*state.asks, false),
             AccountMeta::new(*wallet, false),
-            AccountMeta::new_readonly(payer.pubkey(), true),
+            AccountMeta::new_readonly(*owner, true),
             AccountMeta::new(*state.coin_vault, false),
             AccountMeta::new(*state.pc_vault, false),
             AccountMeta::new_readonly(spl_token::ID, false),
             AccountMeta::new_readonly(solana_sdk::sysvar::rent::ID, false),
         ],
-    };
+    }
+}
 
-    instructions.push(instruction);
-    signers.push(payer);
+/// Cancels the order with the given client order id and places the replacement
+/// in the same transaction, so the book is never left without a quote.
+#[allow(clippy::too_many_arguments)]
+pub fn amend_order(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    payer: &Keypair,
+    wallet: &Pubkey,
+    state: &MarketPubkeys,
+    orders: &Pubkey,
+    client_order_id: u64,
+    new_order: NewOrderInstructionV3,
+) -> Result<Signature> {
+    let mut client_order_ids = [0u64; 8];
+    client_order_ids[0] = client_order_id;
+
+    let instructions = vec![
+        cancel_order_by_client_order_ids_ix(
+            program_id,
+            &state.market,
+            &state.bids,
+            &state.asks,
+            orders,
+            &payer.pubkey(),
+            &state.event_q,
+            client_order_ids,
+        )?,
+        new_order_instruction(
+            program_id,
+            &payer.pubkey(),
+            wallet,
+            state,
+            orders,
+            new_order,
+        ),
+    ];
 
     let recent_hash = client.get_latest_blockhash()?;
     let txn = Transaction::new_signed_with_payer(
         &instructions,
         Some(&payer.pubkey()),
-        &signers,
+        &[payer],
         recent_hash,
     );
 
-    let _signature = client.send_and_confirm_transaction(&txn)?;
+    let signature = client.send_and_confirm_transaction(&txn)?;
+
+    Ok(signature)
+}
+
+/// What happened to an order once its transaction was confirmed.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct OrderOutcome {
+    /// Order id of the remainder resting in the book, if any.
+    pub resting_order_id: Option<u128>,
+    pub fills: usize,
+    /// Filled quantity in coin lots, as a taker and as a maker.
+    pub filled_size: u64,
+    pub native_qty_paid: u64,
+    pub native_qty_received: u64,
+    /// Fees paid as a taker.
+    pub native_fees: u64,
+}
+
+impl OrderOutcome {
+    pub fn is_filled(&self) -> bool {
+        self.fills > 0
+    }
+
+    pub fn is_resting(&self) -> bool {
+        self.resting_order_id.is_some()
+    }
+}
+
+/// Looks up the order with the given client order id in the OpenOrders account and
+/// collects its fills from the event queue. Both are read from one snapshot, so they
+/// belong to the same slot.
+pub fn get_order_outcome(
+    client: &RpcClient,
+    market_keys: &MarketPubkeys,
+    orders: &Pubkey,
+    client_order_id: u64,
+    coin_lot_size: u64,
+) -> Result<OrderOutcome> {
+    let snapshot = load_market_snapshot(client, market_keys, Some(orders))?;
+    let open_orders = snapshot
+        .open_orders()?
+        .ok_or_else(|| format_err!("open orders account {} not found", orders))?;
+
+    let (header, events) = snapshot.event_queue()?;
+    let events = decode_events(market_keys, header, &events, Some(orders))?;
+
+    Ok(order_outcome(
+        &open_orders,
+        &events.events,
+        client_order_id,
+        coin_lot_size,
+    ))
+}
+
+/// Outcome of the order with the given client order id, taken from its OpenOrders
+/// account and the events of that account.
+pub fn order_outcome(
+    open_orders: &OpenOrders,
+    events: &[EventOutput],
+    client_order_id: u64,
+    coin_lot_size: u64,
+) -> OrderOutcome {
+    let mut outcome = OrderOutcome {
+        resting_order_id: open_order_slots(open_orders)
+            .into_iter()
+            .find(|order| order.client_order_id == client_order_id)
+            .map(|order| order.order_id),
+        ..OrderOutcome::default()
+    };
+
+    let mut native_coin_filled = 0;
+    for event in events {
+        if let EventOutput::Fill {
+            side,
+            maker,
+            native_qty_paid,
+            native_qty_received,
+            native_fee_or_rebate,
+            client_order_id: Some(fill_client_order_id),
+            ..
+        } = event
+        {
+            if *fill_client_order_id != client_order_id {
+                continue;
+            }
+
+            outcome.fills += 1;
+            outcome.native_qty_paid += native_qty_paid;
+            outcome.native_qty_received += native_qty_received;
+            if !maker {
+                outcome.native_fees += native_fee_or_rebate;
+            }
+
+            native_coin_filled += match side {
+                OrderSide::Lend => native_qty_paid,
+                OrderSide::Borrow => native_qty_received,
+            };
+        }
+    }
+
+    outcome.filled_size = native_coin_filled / coin_lot_size.max(1);
+    outcome
+}
+
+/// Decodes the events left in the event queue of the market, oldest first. When `orders`
+/// is given only the events of that OpenOrders account are kept.
+pub fn get_events(
+    client: &RpcClient,
+    market_keys: &MarketPubkeys,
+    orders: Option<&Pubkey>,
+) -> Result<EventsOutput> {
+    let (header, events) = load_event_queue(client, &market_keys.event_q)?;
+    decode_events(market_keys, header, &events, orders)
+}
+
+/// Decodes events loaded from the event queue of the market, see `get_events`.
+pub fn decode_events(
+    market_keys: &MarketPubkeys,
+    header: QueueHeader,
+    events: &[Event],
+    orders: Option<&Pubkey>,
+) -> Result<EventsOutput> {
+    let first_seq_num = header.seq_num.wrapping_sub(header.count);
+
+    let mut decoded = Vec::new();
+    for (index, event) in events.iter().enumerate() {
+        let view = event
+            .as_view()
+            .map_err(|e| format_err!("failed to decode event: {:?}", e))?;
+        let event = EventOutput::new(first_seq_num.wrapping_add(index as u64), view);
+
+        match orders {
+            Some(orders) if event.owner() != orders.to_string() => continue,
+            _ => decoded.push(event),
+        }
+    }
+
+    Ok(EventsOutput {
+        event_queue: market_keys.event_q.to_string(),
+        head: header.head,
+        count: header.count,
+        seq_num: header.seq_num,
+        open_orders: orders.map(|orders| orders.to_string()),
+        events: decoded,
+    })
+}
+
+/// Decodes the requests of the market which are waiting to be matched, oldest first.
+pub fn get_requests(client: &RpcClient, market_keys: &MarketPubkeys) -> Result<RequestsOutput> {
+    let (header, requests) = load_request_queue(client, &market_keys.req_q)?;
+    let first_seq_num = header.seq_num.wrapping_sub(header.count);
+
+    let mut decoded = Vec::new();
+    for (index, request) in requests.iter().enumerate() {
+        let view = request
+            .as_view()
+            .map_err(|e| format_err!("failed to decode request: {:?}", e))?;
+        // the flags are the first byte of a request but aren't exposed by the DEX
+        let flags = bytemuck::bytes_of(request)[0];
+
+        decoded.push(RequestOutput::new(
+            first_seq_num.wrapping_add(index as u64),
+            flags,
+            view,
+        ));
+    }
+
+    Ok(RequestsOutput {
+        request_queue: market_keys.req_q.to_string(),
+        head: header.head,
+        count: header.count,
+        seq_num: header.seq_num,
+        requests: decoded,
+    })
+}
+
+/// Resting orders of both sides of the book, best price first.
+#[derive(Debug, Default, Clone)]
+pub struct Orderbook {
+    pub asks: Vec<LeafNode>,
+    pub bids: Vec<LeafNode>,
+}
+
+/// Loads the resting orders of the market. When `limit` is given only that many of
+/// the best orders of each side are loaded, which is enough for top-of-book queries.
+pub fn load_orderbook(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    market_pk: &Pubkey,
+    limit: Option<usize>,
+) -> Result<Orderbook> {
+    // the snapshot checks the cached keys against the market account
+    let market_keys = load_market_cache(client, program_id, market_pk)?.market_keys()?;
+    let snapshot = load_market_snapshot(client, &market_keys, None)?;
 
-    Ok(())
+    snapshot.orderbook(limit)
+}
+
+impl<'a> MarketSnapshot<'a> {
+    /// Decodes the resting orders from the bids and asks of the snapshot, see `load_orderbook`.
+    pub fn orderbook(&self, limit: Option<usize>) -> Result<Orderbook> {
+        let asks = decode_slab(&self.asks.data, AccountFlag::Asks)?;
+        let bids = decode_slab(&self.bids.data, AccountFlag::Bids)?;
+
+        Ok(Orderbook {
+            asks: asks.leaves(false, limit)?,
+            bids: bids.leaves(true, limit)?,
+        })
+    }
 }
 
 pub fn fetch_and_show_orders(
     client: &RpcClient,
     program_id: &Pubkey,
     market_pk: &Pubkey,
+    format: OutputFormat,
+    limit: Option<usize>,
 ) -> Result<()> {
-    let market_keys = get_keys_for_market(&client, &program_id, &market_pk)?;
-
-    let market_account: Account = client.get_account(&market_pk)?;
-    let mut lamp = market_account.lamports;
-    let mut data = market_account.data;
-
-    let market_account_info = AccountInfo {
-        key: &market_account.owner,
-        is_signer: false,
-        is_writable: false,
-        lamports: Rc::new(RefCell::new(&mut lamp)),
-        data: Rc::new(RefCell::new(&mut data)),
-        owner: &program_id,
-        executable: market_account.executable,
-        rent_epoch: market_account.rent_epoch,
-    };
-
-    let market = Market::load(&market_account_info, &program_id, false)?;
+    let orderbook = load_orderbook(client, program_id, market_pk, limit)?;
 
-    let ask_key = *market_keys.asks;
-    let ask_acc = client.get_account(&ask_key)?;
+    render(format, &FetchOutput::new(market_pk, &orderbook))
+}
 
-    let ask_owner = ask_acc.owner;
-    let mut ask_lamp = ask_acc.lamports;
-    let mut ask_data = ask_acc.data;
+/// Redraws the orderbook whenever the bids, asks or event queue accounts change. Updates
+/// come from websocket subscriptions at `ws_url`; if those can't be set up or are closed,
+/// the book is polled every `interval` instead. Runs until the process is stopped.
+pub fn watch_and_show_orders(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    market_pk: &Pubkey,
+    format: OutputFormat,
+    limit: Option<usize>,
+    ws_url: &str,
+    interval: Duration,
+) -> Result<()> {
+    let market_keys = get_cached_keys_for_market(client, program_id, market_pk)?;
 
-    let ask_account_info = AccountInfo {
-        key: &ask_key,
-        is_signer: false,
-        is_writable: false,
-        lamports: Rc::new(RefCell::new(&mut ask_lamp)),
-        data: Rc::new(RefCell::new(&mut ask_data)),
-        owner: &ask_owner,
-        executable: ask_acc.executable,
-        rent_epoch: ask_acc.rent_epoch,
-    };
+    let (sender, receiver) = mpsc::channel();
+    let mut subscriptions = Vec::new();
+    let mut polling = false;
 
-    let asks = market.load_asks_mut(&ask_account_info)?;
-    let slab = asks.deref();
-
-    debug_println!("Lending Orders:");
-    for i in 0..slab.capacity() {
-        if let Some(node) = slab.get(i as u32) {
-            if let Some(leaf) = node.as_leaf() {
-                debug_println!("order id: {:?}", leaf.order_id());
-                debug_println!("    client order id: {:?}", leaf.client_order_id());
-                debug_println!("    price: {:?}", leaf.price());
-                debug_println!("    amount: {:?}", leaf.quantity());
-                debug_println!("    lend\n");
+    for account in [&market_keys.bids, &market_keys.asks, &market_keys.event_q] {
+        match PubsubClient::account_subscribe(ws_url, account, None) {
+            Ok((subscription, updates)) => {
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    while updates.recv().is_ok() {
+                        if sender.send(()).is_err() {
+                            break;
+                        }
+                    }
+                });
+                subscriptions.push(subscription);
+            }
+            Err(err) => {
+                eprintln!(
+                    "Unable to subscribe to {}: {}, polling every {} second(s) instead",
+                    ws_url,
+                    err,
+                    interval.as_secs()
+                );
+                polling = true;
+                break;
             }
         }
     }
 
-    let bid_key = *market_keys.bids;
-    let bid_acc = client.get_account(&bid_key)?;
+    // only the subscription threads keep the channel open from now on
+    drop(sender);
 
-    let bid_owner = bid_acc.owner;
-    let mut bid_lamp = bid_acc.lamports;
-    let mut bid_data = bid_acc.data;
-
-    let bid_account_info = AccountInfo {
-        key: &bid_key,
-        is_signer: false,
-        is_writable: false,
-        lamports: Rc::new(RefCell::new(&mut bid_lamp)),
-        data: Rc::new(RefCell::new(&mut bid_data)),
-        owner: &bid_owner,
-        executable: bid_acc.executable,
-        rent_epoch: bid_acc.rent_epoch,
-    };
+    if polling {
+        for mut subscription in subscriptions.drain(..) {
+            let _ = subscription.shutdown();
+        }
+    }
 
-    let bids = market.load_bids_mut(&bid_account_info)?;
-    let slab = bids.deref();
-
-    debug_println!("Borrowing Orders:");
-    for i in 0..slab.capacity() {
-        if let Some(node) = slab.get(i as u32) {
-            if let Some(leaf) = node.as_leaf() {
-                debug_println!("order id: {:?}", leaf.order_id());
-                debug_println!("    client order id: {:?}", leaf.client_order_id());
-                debug_println!("    price: {:?}", leaf.price());
-                debug_println!("    amount: {:?}", leaf.quantity());
-                debug_println!("    borrow\n");
+    loop {
+        let orderbook = load_orderbook(client, program_id, market_pk, limit);
+        match orderbook {
+            Ok(orderbook) => {
+                if format == OutputFormat::Table {
+                    // clear the terminal and move the cursor home to redraw in place
+                    print!("\x1b[2J\x1b[H");
+                }
+                render(format, &FetchOutput::new(market_pk, &orderbook))?;
             }
+            Err(err) => eprintln!("Unable to load orderbook: {}", err),
+        }
+
+        if polling {
+            thread::sleep(interval);
+            continue;
+        }
+
+        if receiver.recv().is_err() {
+            eprintln!(
+                "Subscriptions are closed, polling every {} second(s) instead",
+                interval.as_secs()
+            );
+            polling = true;
+            continue;
         }
+
+        // updates of several accounts usually arrive together, one redraw is enough
+        while receiver.try_recv().is_ok() {}
+    }
+}
+
+/// An order owned by an OpenOrders account.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenOrder {
+    pub slot: u8,
+    pub side: Side,
+    pub order_id: u128,
+    pub client_order_id: u64,
+    /// Quantity left in the book, `None` if the order wasn't found in the book.
+    pub quantity: Option<u64>,
+}
+
+impl OpenOrder {
+    /// Limit price, which the DEX stores in the upper half of the order id.
+    pub fn price(&self) -> u64 {
+        (self.order_id >> 64) as u64
+    }
+}
+
+/// Orders held in the occupied slots of an OpenOrders account.
+pub fn open_order_slots(open_orders: &OpenOrders) -> Vec<OpenOrder> {
+    let free_slot_bits = open_orders.free_slot_bits;
+    let is_bid_bits = open_orders.is_bid_bits;
+    let order_ids = open_orders.orders;
+    let client_order_ids = open_orders.client_order_ids;
+
+    (0..order_ids.len())
+        .filter(|slot| free_slot_bits & (1u128 << slot) == 0)
+        .map(|slot| OpenOrder {
+            slot: slot as u8,
+            side: if is_bid_bits & (1u128 << slot) != 0 {
+                Side::Bid
+            } else {
+                Side::Ask
+            },
+            order_id: order_ids[slot],
+            client_order_id: client_order_ids[slot],
+            quantity: None,
+        })
+        .collect()
+}
+
+/// Balances and orders of an OpenOrders account.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenOrdersSummary {
+    pub address: Pubkey,
+    pub native_coin_free: u64,
+    pub native_coin_total: u64,
+    pub native_pc_free: u64,
+    pub native_pc_total: u64,
+    pub referrer_rebates_accrued: u64,
+    pub orders: Vec<OpenOrder>,
+}
+
+impl OpenOrdersSummary {
+    pub fn native_coin_locked(&self) -> u64 {
+        self.native_coin_total.saturating_sub(self.native_coin_free)
+    }
+
+    pub fn native_pc_locked(&self) -> u64 {
+        self.native_pc_total.saturating_sub(self.native_pc_free)
+    }
+}
+
+pub fn summarize_open_orders(
+    address: &Pubkey,
+    open_orders: &OpenOrders,
+    orderbook: &Orderbook,
+) -> OpenOrdersSummary {
+    let orders = open_order_slots(open_orders)
+        .into_iter()
+        .map(|mut order| {
+            let leaves = match order.side {
+                Side::Ask => &orderbook.asks,
+                Side::Bid => &orderbook.bids,
+            };
+
+            order.quantity = leaves
+                .iter()
+                .find(|leaf| leaf.order_id() == order.order_id)
+                .map(|leaf| leaf.quantity());
+            order
+        })
+        .collect();
+
+    OpenOrdersSummary {
+        address: *address,
+        native_coin_free: open_orders.native_coin_free,
+        native_coin_total: open_orders.native_coin_total,
+        native_pc_free: open_orders.native_pc_free,
+        native_pc_total: open_orders.native_pc_total,
+        referrer_rebates_accrued: open_orders.referrer_rebates_accrued,
+        orders,
     }
+}
 
-    Ok(())
+pub fn get_open_orders_summary(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    market_pk: &Pubkey,
+    orders: &Pubkey,
+) -> Result<OpenOrdersSummary> {
+    // the snapshot checks the cached keys against the market account
+    let market_keys = load_market_cache(client, program_id, market_pk)?.market_keys()?;
+    let snapshot = load_market_snapshot(client, &market_keys, Some(orders))?;
+
+    let orderbook = snapshot.orderbook(None)?;
+    let open_orders = snapshot
+        .open_orders()?
+        .ok_or_else(|| format_err!("open orders account {} not loaded", orders))?;
+
+    Ok(summarize_open_orders(orders, &open_orders, &orderbook))
+}
+
+/// Client order ids of the orders which are still resting in the book.
+pub fn resting_client_order_ids(open_orders: &OpenOrders) -> Vec<u64> {
+    open_order_slots(open_orders)
+        .into_iter()
+        .map(|order| order.client_order_id)
+        .filter(|client_order_id| *client_order_id != 0)
+        .collect()
+}
+
+/// Moves the free balances of the OpenOrders account back to the owner's wallets.
+pub fn settle_funds_instruction(
+    program_id: &Pubkey,
+    state: &MarketPubkeys,
+    orders: &Pubkey,
+    owner: &Pubkey,
+    coin_wallet: &Pubkey,
+    pc_wallet: &Pubkey,
+) -> Result<Instruction> {
+    Ok(settle_funds_ix(
+        program_id,
+        &state.market,
+        &spl_token::ID,
+        orders,
+        owner,
+        &state.coin_vault,
+        coin_wallet,
+        &state.pc_vault,
+        pc_wallet,
+        None,
+        &state.vault_signer_key,
+    )?)
+}
+
+pub fn settle_funds(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    owner: &Keypair,
+    state: &MarketPubkeys,
+    orders: &Pubkey,
+    coin_wallet: &Pubkey,
+    pc_wallet: &Pubkey,
+) -> Result<Signature> {
+    let ixs = &[settle_funds_instruction(
+        program_id,
+        state,
+        orders,
+        &owner.pubkey(),
+        coin_wallet,
+        pc_wallet,
+    )?];
+
+    let recent_hash = client.get_latest_blockhash()?;
+    let txn = Transaction::new_signed_with_payer(ixs, Some(&owner.pubkey()), &[owner], recent_hash);
+
+    let signature = client.send_and_confirm_transaction(&txn)?;
+
+    Ok(signature)
 }
 
 pub fn cancel_order_by_client_order_ids(
@@ -198,7 +902,7 @@ pub fn cancel_order_by_client_order_ids(
     market_keys: &MarketPubkeys,
     orders: &Pubkey,
     client_order_id: [u64; 8],
-) -> Result<()> {
+) -> Result<Signature> {
     let ixs = &[cancel_order_by_client_order_ids_ix(
         program_id,
         &market_keys.market,
@@ -218,7 +922,232 @@ pub fn cancel_order_by_client_order_ids(
         recent_hash
     );
 
-    let _signature = client.send_and_confirm_transaction(&txn)?;
+    let signature = client.send_and_confirm_transaction(&txn)?;
+
+    Ok(signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_order_kind_from_str_ok() {
+        assert_eq!("limit".parse::<OrderKind>().unwrap(), OrderKind::Limit);
+        assert_eq!(
+            "post-only".parse::<OrderKind>().unwrap(),
+            OrderKind::PostOnly
+        );
+        assert_eq!("market".parse::<OrderKind>().is_err(), true);
+
+        let order_type: OrderType = OrderKind::Ioc.into();
+        assert_eq!(order_type, OrderType::ImmediateOrCancel);
+        assert_eq!(OrderKind::from(order_type), OrderKind::Ioc);
+    }
+
+    #[test]
+    fn test_order_builder_defaults_ok() {
+        let order = OrderBuilder::new(Side::Ask).price(5).size(100).build().unwrap();
+
+        assert_eq!(order.limit_price.get(), 5);
+        assert_eq!(order.max_coin_qty.get(), 100);
+        assert_eq!(order.max_native_pc_qty_including_fees.get(), std::u64::MAX);
+        assert_eq!(order.order_type, OrderType::Limit);
+        assert_eq!(order.self_trade_behavior, SelfTradeBehavior::DecrementTake);
+        assert_eq!(order.limit, std::u16::MAX);
+    }
+
+    #[test]
+    fn test_order_builder_zero_size_failed() {
+        let result = OrderBuilder::new(Side::Ask).price(5).size(0).build();
+        assert_eq!(result.is_err(), true);
+
+        let result = OrderBuilder::new(Side::Bid).price(0).size(10).build();
+        assert_eq!(result.is_err(), true);
+    }
+
+    #[test]
+    fn test_order_builder_max_pc_includes_fees_ok() {
+        let builder = OrderBuilder::new(Side::Bid)
+            .price(5)
+            .size(10)
+            .lot_sizes(1_000_000, 10_000);
+
+        let native_pc_qty = 5 * 10 * 10_000;
+        let max_native_pc_qty = builder.max_native_pc_qty().unwrap();
+        assert_eq!(max_native_pc_qty > native_pc_qty, true);
 
-    Ok(())
+        let order = builder.build().unwrap();
+        assert_eq!(order.max_native_pc_qty_including_fees.get(), max_native_pc_qty);
+    }
+
+    #[test]
+    fn test_order_builder_insufficient_balance_failed() {
+        let result = OrderBuilder::new(Side::Ask)
+            .price(5)
+            .size(10)
+            .lot_sizes(1_000_000, 10_000)
+            .balance(9_999_999)
+            .build();
+        assert_eq!(result.is_err(), true);
+
+        let result = OrderBuilder::new(Side::Ask)
+            .price(5)
+            .size(10)
+            .lot_sizes(1_000_000, 10_000)
+            .balance(10_000_000)
+            .build();
+        assert_eq!(result.is_ok(), true);
+    }
+
+    #[test]
+    fn test_order_builder_expired_deadline_failed() {
+        let result = OrderBuilder::new(Side::Ask)
+            .price(5)
+            .size(10)
+            .max_ts(unix_timestamp().unwrap() - 1)
+            .build();
+        assert_eq!(result.is_err(), true);
+    }
+
+    #[test]
+    fn test_order_builder_overflow_failed() {
+        let result = OrderBuilder::new(Side::Bid)
+            .price(std::u64::MAX)
+            .size(2)
+            .build();
+        assert_eq!(result.is_err(), true);
+    }
+
+    fn open_orders_with(slots: &[(usize, Side, u128, u64)]) -> OpenOrders {
+        let mut open_orders: OpenOrders = bytemuck::Zeroable::zeroed();
+        let mut free_slot_bits = !0u128;
+        let mut is_bid_bits = 0u128;
+        let mut order_ids = [0u128; 128];
+        let mut client_order_ids = [0u64; 128];
+
+        for (slot, side, order_id, client_order_id) in slots {
+            free_slot_bits &= !(1u128 << slot);
+            if *side == Side::Bid {
+                is_bid_bits |= 1u128 << slot;
+            }
+            order_ids[*slot] = *order_id;
+            client_order_ids[*slot] = *client_order_id;
+        }
+
+        open_orders.free_slot_bits = free_slot_bits;
+        open_orders.is_bid_bits = is_bid_bits;
+        open_orders.orders = order_ids;
+        open_orders.client_order_ids = client_order_ids;
+        open_orders.native_coin_free = 10;
+        open_orders.native_coin_total = 30;
+        open_orders.native_pc_free = 5;
+        open_orders.native_pc_total = 5;
+        open_orders
+    }
+
+    fn order_id(price: u64, seq_num: u64) -> u128 {
+        ((price as u128) << 64) | seq_num as u128
+    }
+
+    fn fill(side: OrderSide, maker: bool, coin_qty: u64, client_order_id: u64) -> EventOutput {
+        let (native_qty_paid, native_qty_received) = match side {
+            OrderSide::Lend => (coin_qty, 10),
+            OrderSide::Borrow => (10, coin_qty),
+        };
+
+        EventOutput::Fill {
+            seq_num: 1,
+            side,
+            maker,
+            native_qty_paid,
+            native_qty_received,
+            native_fee_or_rebate: 1,
+            order_id: String::new(),
+            owner: String::new(),
+            client_order_id: Some(client_order_id),
+        }
+    }
+
+    #[test]
+    fn test_order_outcome_ok() {
+        // a post-only order which rested and was filled as a maker before the lookup
+        let open_orders = open_orders_with(&[]);
+        let events = vec![
+            fill(OrderSide::Lend, true, 2_000, 42),
+            fill(OrderSide::Lend, true, 1_000, 42),
+            fill(OrderSide::Lend, false, 5_000, 43),
+        ];
+
+        let outcome = order_outcome(&open_orders, &events, 42, 1_000);
+        assert_eq!(outcome.fills, 2);
+        assert_eq!(outcome.filled_size, 3);
+        assert_eq!(outcome.native_fees, 0);
+        assert_eq!(outcome.resting_order_id, None);
+
+        // a borrow order partially filled as a taker with its remainder resting
+        let open_orders = open_orders_with(&[(1, Side::Bid, order_id(5, 9), 44)]);
+        let events = vec![fill(OrderSide::Borrow, false, 1_000, 44)];
+
+        let outcome = order_outcome(&open_orders, &events, 44, 1_000);
+        assert_eq!(outcome.filled_size, 1);
+        assert_eq!(outcome.native_fees, 1);
+        assert_eq!(outcome.resting_order_id, Some(order_id(5, 9)));
+    }
+
+    #[test]
+    fn test_open_order_slots_ok() {
+        let open_orders = open_orders_with(&[
+            (0, Side::Ask, order_id(7, 1), 11),
+            (3, Side::Bid, order_id(5, 2), 12),
+            (127, Side::Ask, order_id(9, 3), 0),
+        ]);
+
+        let orders = open_order_slots(&open_orders);
+        assert_eq!(orders.len(), 3);
+        assert_eq!(orders[0].slot, 0);
+        assert_eq!(orders[0].side, Side::Ask);
+        assert_eq!(orders[0].price(), 7);
+        assert_eq!(orders[0].client_order_id, 11);
+        assert_eq!(orders[1].slot, 3);
+        assert_eq!(orders[1].side, Side::Bid);
+        assert_eq!(orders[1].price(), 5);
+        assert_eq!(orders[2].slot, 127);
+        assert_eq!(orders[2].quantity, None);
+
+        // orders placed without a client order id aren't reported as resting ids
+        assert_eq!(resting_client_order_ids(&open_orders), vec![11, 12]);
+
+        let empty = open_orders_with(&[]);
+        assert_eq!(open_order_slots(&empty).is_empty(), true);
+    }
+
+    #[test]
+    fn test_summarize_open_orders_ok() {
+        let address = Pubkey::new_unique();
+        let open_orders = open_orders_with(&[
+            (0, Side::Ask, order_id(7, 1), 11),
+            (1, Side::Bid, order_id(5, 2), 12),
+            (2, Side::Bid, order_id(4, 3), 13),
+        ]);
+
+        // the ask is in the book, the first bid was filled in the meantime, and a bid
+        // with the id of the second bid on the ask side mustn't be matched
+        let leaf = |key: u128, quantity: u64, client_order_id: u64| {
+            LeafNode::new(0, key, [0; 4], quantity, FeeTier::Base, client_order_id)
+        };
+        let orderbook = Orderbook {
+            asks: vec![leaf(order_id(7, 1), 40, 11), leaf(order_id(4, 3), 8, 13)],
+            bids: vec![leaf(order_id(6, 4), 1, 0)],
+        };
+
+        let summary = summarize_open_orders(&address, &open_orders, &orderbook);
+        assert_eq!(summary.address, address);
+        assert_eq!(summary.native_coin_locked(), 20);
+        assert_eq!(summary.native_pc_locked(), 0);
+        assert_eq!(summary.orders.len(), 3);
+        assert_eq!(summary.orders[0].quantity, Some(40));
+        assert_eq!(summary.orders[1].quantity, None);
+        assert_eq!(summary.orders[2].quantity, None);
+    }
 }
@@ -0,0 +1,1429 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::Serialize;
+use serum_dex::{
+    critbit::LeafNode,
+    state::{EventView, RequestView},
+};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{
+    explorer_link, FillRecord, MarketOverview, MarketPubkeys, OpenOrder, OpenOrdersSummary,
+    OrderKind, OrderOutcome, OrderSide, Orderbook,
+};
+
+/// How command results are printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    JsonCompact,
+}
+
+/// A command result which can be printed for humans or serialized for scripts.
+pub trait Render: Serialize {
+    fn render_table(&self);
+}
+
+pub fn render<T: Render>(format: OutputFormat, output: &T) -> Result<()> {
+    match format {
+        OutputFormat::Table => output.render_table(),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(output)?),
+        OutputFormat::JsonCompact => println!("{}", serde_json::to_string(output)?),
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ErrorOutput {
+    pub error: String,
+}
+
+impl Render for ErrorOutput {
+    fn render_table(&self) {
+        eprintln!("{}", self.error);
+    }
+}
+
+/// Prints an error message in the requested format to stderr, so it doesn't mix with
+/// the results a script reads from stdout.
+pub fn render_error(format: OutputFormat, error: impl ToString) {
+    let output = ErrorOutput {
+        error: error.to_string(),
+    };
+
+    let json = match format {
+        OutputFormat::Table => return output.render_table(),
+        OutputFormat::Json => serde_json::to_string_pretty(&output),
+        OutputFormat::JsonCompact => serde_json::to_string(&output),
+    };
+
+    match json {
+        Ok(json) => eprintln!("{}", json),
+        Err(err) => eprintln!("{}", err),
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct MarketKeysOutput {
+    pub market: String,
+    pub request_queue: String,
+    pub event_queue: String,
+    pub bids: String,
+    pub asks: String,
+    pub coin_vault: String,
+    pub pc_vault: String,
+    pub vault_signer: String,
+}
+
+impl From<&MarketPubkeys> for MarketKeysOutput {
+    fn from(keys: &MarketPubkeys) -> Self {
+        MarketKeysOutput {
+            market: keys.market.to_string(),
+            request_queue: keys.req_q.to_string(),
+            event_queue: keys.event_q.to_string(),
+            bids: keys.bids.to_string(),
+            asks: keys.asks.to_string(),
+            coin_vault: keys.coin_vault.to_string(),
+            pc_vault: keys.pc_vault.to_string(),
+            vault_signer: keys.vault_signer_key.to_string(),
+        }
+    }
+}
+
+impl MarketKeysOutput {
+    fn render_table(&self) {
+        println!("    market: {}", self.market);
+        println!("    request queue: {}", self.request_queue);
+        println!("    event queue: {}", self.event_queue);
+        println!("    bids: {}", self.bids);
+        println!("    asks: {}", self.asks);
+        println!("    coin vault: {}", self.coin_vault);
+        println!("    pc vault: {}", self.pc_vault);
+        println!("    vault signer: {}", self.vault_signer);
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct InitOutput {
+    pub market: MarketKeysOutput,
+    pub coin_mint: String,
+    pub pc_mint: String,
+    pub url: String,
+    pub program_id: String,
+    pub wallet: String,
+    pub signatures: Vec<String>,
+}
+
+impl Render for InitOutput {
+    fn render_table(&self) {
+        println!("Market is initialized, created accounts:");
+        self.market.render_table();
+        println!("Coin mint: {}", self.coin_mint);
+        println!("Pc mint: {}", self.pc_mint);
+        for signature in self.signatures.iter() {
+            println!("Signature: {}", signature);
+            println!("    {}", explorer_link(signature, &self.url));
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct AttachOutput {
+    pub market: MarketKeysOutput,
+    pub coin_mint: String,
+    pub pc_mint: String,
+    pub coin_lot_size: u64,
+    pub pc_lot_size: u64,
+    pub fee_rate_bps: u64,
+    pub url: String,
+    pub program_id: String,
+    pub wallet: String,
+}
+
+impl Render for AttachOutput {
+    fn render_table(&self) {
+        println!("Attached to market {}", self.market.market);
+        println!("Coin mint: {}", self.coin_mint);
+        println!("Pc mint: {}", self.pc_mint);
+        println!(
+            "Lot sizes: coin {}, pc {}",
+            self.coin_lot_size, self.pc_lot_size
+        );
+        println!("Fee rate bps: {}", self.fee_rate_bps);
+    }
+}
+
+/// Orders of one side of the book aggregated by price.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct BookLevel {
+    pub price: u64,
+    pub quantity: u64,
+    pub orders: usize,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct BookOrder {
+    /// Order ids don't fit into a JSON number, so they are written as strings.
+    pub order_id: String,
+    pub client_order_id: u64,
+    pub price: u64,
+    pub quantity: u64,
+    pub owner: String,
+}
+
+impl From<&LeafNode> for BookOrder {
+    fn from(leaf: &LeafNode) -> Self {
+        BookOrder {
+            order_id: leaf.order_id().to_string(),
+            client_order_id: leaf.client_order_id(),
+            price: leaf.price().get(),
+            quantity: leaf.quantity(),
+            owner: owner_pubkey(leaf.owner()),
+        }
+    }
+}
+
+/// Aggregates orders, which must be sorted by price, into price levels.
+pub fn book_levels(orders: &[BookOrder]) -> Vec<BookLevel> {
+    let mut levels: Vec<BookLevel> = Vec::new();
+
+    for order in orders {
+        match levels.last_mut() {
+            Some(level) if level.price == order.price => {
+                level.quantity += order.quantity;
+                level.orders += 1;
+            }
+            _ => levels.push(BookLevel {
+                price: order.price,
+                quantity: order.quantity,
+                orders: 1,
+            }),
+        }
+    }
+
+    levels
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct FetchOutput {
+    pub market: String,
+    /// Lending orders, best rate first.
+    pub asks: Vec<BookOrder>,
+    /// Borrowing orders, best rate first.
+    pub bids: Vec<BookOrder>,
+    pub ask_levels: Vec<BookLevel>,
+    pub bid_levels: Vec<BookLevel>,
+}
+
+impl FetchOutput {
+    pub fn new(market: &Pubkey, orderbook: &Orderbook) -> Self {
+        let asks: Vec<BookOrder> = orderbook.asks.iter().map(BookOrder::from).collect();
+        let bids: Vec<BookOrder> = orderbook.bids.iter().map(BookOrder::from).collect();
+
+        FetchOutput {
+            market: market.to_string(),
+            ask_levels: book_levels(&asks),
+            bid_levels: book_levels(&bids),
+            asks,
+            bids,
+        }
+    }
+
+    /// Keeps only the best `depth` price levels of each side and the orders at them.
+    pub fn truncate(&mut self, depth: usize) {
+        self.ask_levels.truncate(depth);
+        self.bid_levels.truncate(depth);
+
+        let asks: usize = self.ask_levels.iter().map(|level| level.orders).sum();
+        let bids: usize = self.bid_levels.iter().map(|level| level.orders).sum();
+        self.asks.truncate(asks);
+        self.bids.truncate(bids);
+    }
+}
+
+impl Render for FetchOutput {
+    fn render_table(&self) {
+        println!("Lending Orders:");
+        for order in self.asks.iter() {
+            render_book_order(order, "lend");
+        }
+
+        println!("Borrowing Orders:");
+        for order in self.bids.iter() {
+            render_book_order(order, "borrow");
+        }
+    }
+}
+
+fn render_book_order(order: &BookOrder, side: &str) {
+    println!("order id: {}", order.order_id);
+    println!("    client order id: {}", order.client_order_id);
+    println!("    price: {}", order.price);
+    println!("    amount: {}", order.quantity);
+    println!("    {}\n", side);
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct OrderOutcomeOutput {
+    pub resting_order_id: Option<String>,
+    pub fills: usize,
+    /// Filled quantity in coin lots.
+    pub filled_size: u64,
+    pub native_qty_paid: u64,
+    pub native_qty_received: u64,
+    pub native_fees: u64,
+}
+
+impl From<&OrderOutcome> for OrderOutcomeOutput {
+    fn from(outcome: &OrderOutcome) -> Self {
+        OrderOutcomeOutput {
+            resting_order_id: outcome
+                .resting_order_id
+                .map(|order_id| order_id.to_string()),
+            fills: outcome.fills,
+            filled_size: outcome.filled_size,
+            native_qty_paid: outcome.native_qty_paid,
+            native_qty_received: outcome.native_qty_received,
+            native_fees: outcome.native_fees,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct OrderOutput {
+    pub side: OrderSide,
+    pub order_type: OrderKind,
+    pub client_order_id: u64,
+    pub size: u64,
+    pub rate: u64,
+    pub signature: String,
+    pub explorer_link: String,
+    pub open_orders: String,
+    /// Signature of the transaction which created the OpenOrders account, if it was
+    /// created for this order.
+    pub open_orders_signature: Option<String>,
+    pub replaced_client_order_id: Option<u64>,
+    pub expires_at: Option<i64>,
+    pub outcome: Option<OrderOutcomeOutput>,
+    /// Set when the OpenOrders account was re-adopted or the order couldn't be recorded.
+    pub warnings: Vec<String>,
+}
+
+impl Render for OrderOutput {
+    fn render_table(&self) {
+        for warning in self.warnings.iter() {
+            println!("Warning: {}", warning);
+        }
+
+        if let Some(replaced_client_order_id) = self.replaced_client_order_id {
+            println!("Order {} is replaced", replaced_client_order_id);
+        }
+
+        if let Some(open_orders_signature) = &self.open_orders_signature {
+            println!(
+                "Open orders account {} is created with signature {}",
+                self.open_orders, open_orders_signature
+            );
+        }
+
+        println!("Client order id: {}", self.client_order_id);
+        println!("Signature: {}", self.signature);
+        println!("    {}", self.explorer_link);
+
+        if let Some(expires_at) = self.expires_at {
+            println!(
+                "Order is good till {}, run sweep-expired to cancel it afterwards",
+                expires_at
+            );
+        }
+
+        let outcome = match &self.outcome {
+            Some(outcome) => outcome,
+            None => return,
+        };
+
+        if outcome.fills > 0 {
+            println!(
+                "Order matched in {} fill(s) for {} of {} lots: paid {}, received {}, fees {}",
+                outcome.fills,
+                outcome.filled_size,
+                self.size,
+                outcome.native_qty_paid,
+                outcome.native_qty_received,
+                outcome.native_fees
+            );
+        }
+
+        // a resting order can be filled before the outcome is looked up, so the filled
+        // size tells what happened rather than whether the order still rests
+        match (self.order_type, &outcome.resting_order_id) {
+            _ if outcome.filled_size >= self.size => println!("Order was fully filled"),
+            (_, Some(order_id)) => {
+                println!("New order is placed in Orderbook with id {}", order_id)
+            }
+            (OrderKind::PostOnly, None) if outcome.fills == 0 => {
+                println!("Post-only order was rejected because it would have matched immediately");
+            }
+            (OrderKind::Ioc, None) if outcome.fills == 0 => {
+                println!("Immediate-or-cancel order wasn't filled and has been cancelled");
+            }
+            (OrderKind::Ioc, None) => {
+                println!("Unfilled remainder of the immediate-or-cancel order has been cancelled");
+            }
+            (_, None) => println!("Order is no longer in the book"),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct OpenOrderOutput {
+    pub side: OrderSide,
+    pub order_id: String,
+    pub client_order_id: u64,
+    pub rate: u64,
+    pub quantity: Option<u64>,
+}
+
+impl From<&OpenOrder> for OpenOrderOutput {
+    fn from(order: &OpenOrder) -> Self {
+        OpenOrderOutput {
+            side: order.side.into(),
+            order_id: order.order_id.to_string(),
+            client_order_id: order.client_order_id,
+            rate: order.price(),
+            quantity: order.quantity,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct OpenOrdersOutput {
+    pub address: String,
+    pub native_coin_free: u64,
+    pub native_coin_locked: u64,
+    pub native_coin_total: u64,
+    pub native_pc_free: u64,
+    pub native_pc_locked: u64,
+    pub native_pc_total: u64,
+    pub referrer_rebates_accrued: u64,
+    pub orders: Vec<OpenOrderOutput>,
+}
+
+impl From<&OpenOrdersSummary> for OpenOrdersOutput {
+    fn from(summary: &OpenOrdersSummary) -> Self {
+        OpenOrdersOutput {
+            address: summary.address.to_string(),
+            native_coin_free: summary.native_coin_free,
+            native_coin_locked: summary.native_coin_locked(),
+            native_coin_total: summary.native_coin_total,
+            native_pc_free: summary.native_pc_free,
+            native_pc_locked: summary.native_pc_locked(),
+            native_pc_total: summary.native_pc_total,
+            referrer_rebates_accrued: summary.referrer_rebates_accrued,
+            orders: summary.orders.iter().map(OpenOrderOutput::from).collect(),
+        }
+    }
+}
+
+impl Render for OpenOrdersOutput {
+    fn render_table(&self) {
+        println!("Open orders account: {}", self.address);
+        println!(
+            "Coin: free {}, locked {}, total {}",
+            self.native_coin_free, self.native_coin_locked, self.native_coin_total
+        );
+        println!(
+            "Pc: free {}, locked {}, total {}",
+            self.native_pc_free, self.native_pc_locked, self.native_pc_total
+        );
+        println!(
+            "Referrer rebates accrued: {}",
+            self.referrer_rebates_accrued
+        );
+
+        if self.orders.is_empty() {
+            println!("There are no open orders");
+        }
+
+        for order in self.orders.iter() {
+            let quantity = match order.quantity {
+                Some(quantity) => quantity.to_string(),
+                None => String::from("not in book"),
+            };
+
+            println!(
+                "{} order, client order id: {}, order id: {}, rate: {}, size: {}",
+                side_label(order.side),
+                order.client_order_id,
+                order.order_id,
+                order.rate,
+                quantity
+            );
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct MarketOutput {
+    pub keys: MarketKeysOutput,
+    pub coin_mint: String,
+    pub coin_decimals: u8,
+    pub pc_mint: String,
+    pub pc_decimals: u8,
+    pub coin_lot_size: u64,
+    pub pc_lot_size: u64,
+    pub fee_rate_bps: u64,
+    pub coin_vault_balance: u64,
+    pub pc_vault_balance: u64,
+    pub coin_deposits_total: u64,
+    pub pc_deposits_total: u64,
+    pub coin_fees_accrued: u64,
+    pub pc_fees_accrued: u64,
+    pub referrer_rebates_accrued: u64,
+    pub pc_dust_threshold: u64,
+}
+
+impl From<&MarketOverview> for MarketOutput {
+    fn from(overview: &MarketOverview) -> Self {
+        MarketOutput {
+            keys: MarketKeysOutput::from(&overview.keys),
+            coin_mint: overview.info.coin_mint.to_string(),
+            coin_decimals: overview.coin_decimals,
+            pc_mint: overview.info.pc_mint.to_string(),
+            pc_decimals: overview.pc_decimals,
+            coin_lot_size: overview.info.coin_lot_size,
+            pc_lot_size: overview.info.pc_lot_size,
+            fee_rate_bps: overview.info.fee_rate_bps,
+            coin_vault_balance: overview.coin_vault_balance,
+            pc_vault_balance: overview.pc_vault_balance,
+            coin_deposits_total: overview.coin_deposits_total,
+            pc_deposits_total: overview.pc_deposits_total,
+            coin_fees_accrued: overview.coin_fees_accrued,
+            pc_fees_accrued: overview.pc_fees_accrued,
+            referrer_rebates_accrued: overview.referrer_rebates_accrued,
+            pc_dust_threshold: overview.pc_dust_threshold,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct WalletOutput {
+    pub pubkey: String,
+    pub lamports: u64,
+    pub coin_balance: u64,
+    pub pc_balance: u64,
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct InfoOutput {
+    /// Config entries keyed by their file name without extension.
+    pub config: BTreeMap<String, String>,
+    pub market: Option<MarketOutput>,
+    pub wallet: Option<WalletOutput>,
+    pub open_orders: Option<OpenOrdersOutput>,
+    /// Why the on-chain part is missing, if it is.
+    pub error: Option<String>,
+}
+
+impl Render for InfoOutput {
+    fn render_table(&self) {
+        if self.config.is_empty() {
+            println!("There is no information!");
+            return;
+        }
+
+        for (key, value) in self.config.iter() {
+            println!("{}: {}", config_label(key), value);
+        }
+
+        if let Some(market) = &self.market {
+            println!("\nMarket accounts:");
+            market.keys.render_table();
+
+            println!("\nMarket state:");
+            println!(
+                "    coin mint: {} ({} decimals)",
+                market.coin_mint, market.coin_decimals
+            );
+            println!(
+                "    pc mint: {} ({} decimals)",
+                market.pc_mint, market.pc_decimals
+            );
+            println!(
+                "    lot sizes: coin {}, pc {}",
+                market.coin_lot_size, market.pc_lot_size
+            );
+            println!("    fee rate bps: {}", market.fee_rate_bps);
+            println!(
+                "    vault balances: coin {}, pc {}",
+                market.coin_vault_balance, market.pc_vault_balance
+            );
+            println!(
+                "    deposits total: coin {}, pc {}",
+                market.coin_deposits_total, market.pc_deposits_total
+            );
+            println!(
+                "    fees accrued: coin {}, pc {}",
+                market.coin_fees_accrued, market.pc_fees_accrued
+            );
+            println!(
+                "    referrer rebates accrued: {}",
+                market.referrer_rebates_accrued
+            );
+            println!("    pc dust threshold: {}", market.pc_dust_threshold);
+        }
+
+        if let Some(wallet) = &self.wallet {
+            println!("\nWallet {}:", wallet.pubkey);
+            println!("    SOL: {} lamports", wallet.lamports);
+            println!("    coin: {}", wallet.coin_balance);
+            println!("    pc: {}", wallet.pc_balance);
+        }
+
+        if let Some(open_orders) = &self.open_orders {
+            println!("\nOpen orders account {}:", open_orders.address);
+            println!(
+                "    coin: free {}, locked {}",
+                open_orders.native_coin_free, open_orders.native_coin_locked
+            );
+            println!(
+                "    pc: free {}, locked {}",
+                open_orders.native_pc_free, open_orders.native_pc_locked
+            );
+            println!("    orders: {}", open_orders.orders.len());
+        }
+
+        if let Some(error) = &self.error {
+            println!("Unable to fetch on-chain information: {}", error);
+        }
+    }
+}
+
+fn config_label(key: &str) -> String {
+    match key {
+        "coin_mint" => String::from("Coin mint"),
+        "pc_mint" => String::from("Pc mint"),
+        "wallet" => String::from("Wallet"),
+        "url" => String::from("URL"),
+        "program_id" => String::from("Program ID"),
+        "market_pubkey" => String::from("Market pubkey"),
+        "coin_lot_size" => String::from("Coin lot size"),
+        "pc_lot_size" => String::from("Pc lot size"),
+        "fee_rate_bps" => String::from("Fee rate bps"),
+        key if key.ends_with("_open_order_pubkey") => String::from("Open order pubkey"),
+        key => key.replace('_', " "),
+    }
+}
+
+/// An event of the event queue, fills are reported to both the maker and the taker.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum EventOutput {
+    Fill {
+        seq_num: u64,
+        side: OrderSide,
+        maker: bool,
+        native_qty_paid: u64,
+        native_qty_received: u64,
+        native_fee_or_rebate: u64,
+        order_id: String,
+        owner: String,
+        client_order_id: Option<u64>,
+    },
+    Out {
+        seq_num: u64,
+        side: OrderSide,
+        release_funds: bool,
+        native_qty_released: u64,
+        native_qty_still_locked: u64,
+        order_id: String,
+        owner: String,
+        client_order_id: Option<u64>,
+    },
+}
+
+impl EventOutput {
+    pub fn new(seq_num: u64, view: EventView) -> Self {
+        match view {
+            EventView::Fill {
+                side,
+                maker,
+                native_qty_paid,
+                native_qty_received,
+                native_fee_or_rebate,
+                order_id,
+                owner,
+                client_order_id,
+                ..
+            } => EventOutput::Fill {
+                seq_num,
+                side: side.into(),
+                maker,
+                native_qty_paid,
+                native_qty_received,
+                native_fee_or_rebate,
+                order_id: order_id.to_string(),
+                owner: owner_pubkey(owner),
+                client_order_id: client_order_id.map(|client_order_id| client_order_id.get()),
+            },
+            EventView::Out {
+                side,
+                release_funds,
+                native_qty_unlocked,
+                native_qty_still_locked,
+                order_id,
+                owner,
+                client_order_id,
+                ..
+            } => EventOutput::Out {
+                seq_num,
+                side: side.into(),
+                release_funds,
+                native_qty_released: native_qty_unlocked,
+                native_qty_still_locked,
+                order_id: order_id.to_string(),
+                owner: owner_pubkey(owner),
+                client_order_id: client_order_id.map(|client_order_id| client_order_id.get()),
+            },
+        }
+    }
+
+    pub fn owner(&self) -> &str {
+        match self {
+            EventOutput::Fill { owner, .. } => owner,
+            EventOutput::Out { owner, .. } => owner,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct EventsOutput {
+    pub event_queue: String,
+    pub head: u64,
+    pub count: u64,
+    pub seq_num: u64,
+    /// Open orders account the events are filtered to, if any.
+    pub open_orders: Option<String>,
+    /// Events still in the queue, oldest first.
+    pub events: Vec<EventOutput>,
+}
+
+impl Render for EventsOutput {
+    fn render_table(&self) {
+        println!("Event queue {}:", self.event_queue);
+        println!(
+            "    head: {}, count: {}, next sequence number: {}",
+            self.head, self.count, self.seq_num
+        );
+        if let Some(open_orders) = &self.open_orders {
+            println!("    showing events of open orders account {}", open_orders);
+        }
+
+        if self.events.is_empty() {
+            println!("There are no events");
+        }
+
+        for event in self.events.iter() {
+            match event {
+                EventOutput::Fill {
+                    seq_num,
+                    side,
+                    maker,
+                    native_qty_paid,
+                    native_qty_received,
+                    native_fee_or_rebate,
+                    order_id,
+                    owner,
+                    client_order_id,
+                } => {
+                    let role = if *maker { "maker" } else { "taker" };
+                    println!("#{} fill, {} {}", seq_num, side_label(*side), role);
+                    println!(
+                        "    paid {}, received {}, {} {}",
+                        native_qty_paid,
+                        native_qty_received,
+                        if *maker { "rebate" } else { "fee" },
+                        native_fee_or_rebate
+                    );
+                    render_queue_order(order_id, owner, client_order_id);
+                }
+                EventOutput::Out {
+                    seq_num,
+                    side,
+                    release_funds,
+                    native_qty_released,
+                    native_qty_still_locked,
+                    order_id,
+                    owner,
+                    client_order_id,
+                } => {
+                    println!("#{} out, {}", seq_num, side_label(*side));
+                    println!(
+                        "    released {}, still locked {}, funds released: {}",
+                        native_qty_released, native_qty_still_locked, release_funds
+                    );
+                    render_queue_order(order_id, owner, client_order_id);
+                }
+            }
+        }
+    }
+}
+
+fn owner_pubkey(owner: [u64; 4]) -> String {
+    Pubkey::new(safe_transmute::transmute_to_bytes(&owner[..])).to_string()
+}
+
+/// Name of the side as it's printed in tables and exports.
+pub fn side_label(side: OrderSide) -> &'static str {
+    match side {
+        OrderSide::Lend => "lend",
+        OrderSide::Borrow => "borrow",
+    }
+}
+
+fn render_queue_order(order_id: &str, owner: &str, client_order_id: &Option<u64>) {
+    println!("    order id: {}", order_id);
+    println!("    owner: {}", owner);
+    if let Some(client_order_id) = client_order_id {
+        println!("    client order id: {}", client_order_id);
+    }
+}
+
+/// A request waiting in the request queue until the market is cranked.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum RequestOutput {
+    NewOrder {
+        seq_num: u64,
+        flags: Vec<String>,
+        side: OrderSide,
+        order_type: OrderKind,
+        owner_slot: u8,
+        rate: u64,
+        max_coin_qty: u64,
+        native_pc_qty_locked: Option<u64>,
+        order_id: String,
+        owner: String,
+        client_order_id: Option<u64>,
+    },
+    CancelOrder {
+        seq_num: u64,
+        flags: Vec<String>,
+        side: OrderSide,
+        owner_slot: u8,
+        order_id: String,
+        owner: String,
+        client_order_id: Option<u64>,
+    },
+}
+
+/// Names of the request flags of the DEX which are set.
+pub fn request_flag_names(flags: u8) -> Vec<String> {
+    let names = [
+        (0x01, "new-order"),
+        (0x02, "cancel-order"),
+        (0x04, "bid"),
+        (0x08, "post-only"),
+        (0x10, "immediate-or-cancel"),
+        (0x20, "decrement-take-on-self-trade"),
+    ];
+
+    names
+        .iter()
+        .filter(|(bit, _)| flags & bit != 0)
+        .map(|(_, name)| name.to_string())
+        .collect()
+}
+
+impl RequestOutput {
+    pub fn new(seq_num: u64, flags: u8, view: RequestView) -> Self {
+        let flags = request_flag_names(flags);
+
+        match view {
+            RequestView::NewOrder {
+                side,
+                order_type,
+                owner_slot,
+                order_id,
+                max_coin_qty,
+                native_pc_qty_locked,
+                owner,
+                client_order_id,
+                ..
+            } => RequestOutput::NewOrder {
+                seq_num,
+                flags,
+                side: side.into(),
+                order_type: order_type.into(),
+                owner_slot,
+                rate: (order_id >> 64) as u64,
+                max_coin_qty: max_coin_qty.get(),
+                native_pc_qty_locked: native_pc_qty_locked.map(|qty| qty.get()),
+                order_id: order_id.to_string(),
+                owner: owner_pubkey(owner),
+                client_order_id: client_order_id.map(|client_order_id| client_order_id.get()),
+            },
+            RequestView::CancelOrder {
+                side,
+                order_id,
+                expected_owner_slot,
+                expected_owner,
+                client_order_id,
+                ..
+            } => RequestOutput::CancelOrder {
+                seq_num,
+                flags,
+                side: side.into(),
+                owner_slot: expected_owner_slot,
+                order_id: order_id.to_string(),
+                owner: owner_pubkey(expected_owner),
+                client_order_id: client_order_id.map(|client_order_id| client_order_id.get()),
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RequestsOutput {
+    pub request_queue: String,
+    pub head: u64,
+    pub count: u64,
+    pub seq_num: u64,
+    /// Pending requests, oldest first.
+    pub requests: Vec<RequestOutput>,
+}
+
+impl Render for RequestsOutput {
+    fn render_table(&self) {
+        println!("Request queue {}:", self.request_queue);
+        println!(
+            "    head: {}, count: {}, next sequence number: {}",
+            self.head, self.count, self.seq_num
+        );
+
+        if self.requests.is_empty() {
+            println!("There are no pending requests");
+        }
+
+        for request in self.requests.iter() {
+            match request {
+                RequestOutput::NewOrder {
+                    seq_num,
+                    flags,
+                    side,
+                    owner_slot,
+                    rate,
+                    max_coin_qty,
+                    native_pc_qty_locked,
+                    order_id,
+                    owner,
+                    client_order_id,
+                    ..
+                } => {
+                    println!("#{} new {} order", seq_num, side_label(*side));
+                    println!("    flags: {}", flags.join(", "));
+                    println!("    rate: {}, max coin qty: {}", rate, max_coin_qty);
+                    if let Some(native_pc_qty_locked) = native_pc_qty_locked {
+                        println!("    native pc qty locked: {}", native_pc_qty_locked);
+                    }
+                    println!("    owner slot: {}", owner_slot);
+                    render_queue_order(order_id, owner, client_order_id);
+                }
+                RequestOutput::CancelOrder {
+                    seq_num,
+                    flags,
+                    side,
+                    owner_slot,
+                    order_id,
+                    owner,
+                    client_order_id,
+                } => {
+                    println!("#{} cancel {} order", seq_num, side_label(*side));
+                    println!("    flags: {}", flags.join(", "));
+                    println!("    owner slot: {}", owner_slot);
+                    render_queue_order(order_id, owner, client_order_id);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct FillsOutput {
+    pub open_orders: String,
+    /// Number of fills found in the event queue which weren't recorded before.
+    pub new_fills: usize,
+    /// Path of the CSV file the fills were exported to, if any.
+    pub csv: Option<String>,
+    pub fills: Vec<FillRecord>,
+}
+
+impl Render for FillsOutput {
+    fn render_table(&self) {
+        println!("Fills of open orders account {}:", self.open_orders);
+
+        if self.fills.is_empty() {
+            println!("There are no fills");
+        }
+
+        for fill in self.fills.iter() {
+            let role = if fill.maker { "maker" } else { "taker" };
+            let fee = if fill.maker { "rebate" } else { "fee" };
+
+            println!(
+                "{} {} {}, rate: {}, size: {}, {}: {}, counterparty: {}",
+                fill.first_seen,
+                side_label(fill.side),
+                role,
+                fill.rate,
+                fill.size,
+                fee,
+                fill.fee,
+                fill.counterparties.join(", ")
+            );
+        }
+
+        println!("{} new fill(s) recorded", self.new_fills);
+        if let Some(csv) = &self.csv {
+            println!("Fills are exported to {}", csv);
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct InstructionOutput {
+    pub index: usize,
+    pub program_id: String,
+    pub name: String,
+    /// Decoded fields of Serum instructions as name and value pairs.
+    pub fields: Vec<(String, String)>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct TokenBalanceOutput {
+    pub account: String,
+    pub mint: String,
+    /// Name of the account, such as coin vault or wallet token account, if it is known.
+    pub label: Option<String>,
+    pub pre: u64,
+    pub post: u64,
+    pub delta: i128,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct TransactionOutput {
+    pub signature: String,
+    pub slot: u64,
+    pub block_time: Option<i64>,
+    pub status: String,
+    pub fee: u64,
+    pub instructions: Vec<InstructionOutput>,
+    pub token_balances: Vec<TokenBalanceOutput>,
+    pub logs: Vec<String>,
+}
+
+impl Render for TransactionOutput {
+    fn render_table(&self) {
+        println!("Transaction {}", self.signature);
+        println!("    slot: {}", self.slot);
+        if let Some(block_time) = self.block_time {
+            println!("    block time: {}", block_time);
+        }
+        println!("    status: {}", self.status);
+        println!("    fee: {} lamports", self.fee);
+
+        println!("\nInstructions:");
+        for instruction in self.instructions.iter() {
+            println!(
+                "#{} {} ({})",
+                instruction.index, instruction.name, instruction.program_id
+            );
+            for (name, value) in instruction.fields.iter() {
+                println!("    {}: {}", name, value);
+            }
+        }
+
+        println!("\nToken balance changes:");
+        if self.token_balances.is_empty() {
+            println!("    none");
+        }
+        for balance in self.token_balances.iter() {
+            let label = match &balance.label {
+                Some(label) => format!(" ({})", label),
+                None => String::new(),
+            };
+
+            println!(
+                "    {}{}: {} -> {} ({:+}), mint {}",
+                balance.account, label, balance.pre, balance.post, balance.delta, balance.mint
+            );
+        }
+
+        println!("\nLogs:");
+        for log in self.logs.iter() {
+            println!("    {}", log);
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum BatchOrderStatus {
+    /// The order didn't pass validation and wasn't sent.
+    Rejected,
+    Placed,
+    /// The transaction carrying the order failed.
+    Failed,
+}
+
+/// Result of one order of a batch file, `index` counting from 1.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct BatchOrderOutput {
+    pub index: usize,
+    pub status: BatchOrderStatus,
+    pub client_order_id: Option<u64>,
+    pub signature: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct BatchOutput {
+    /// OpenOrders account the orders were placed with, `None` if no order was valid.
+    pub open_orders: Option<String>,
+    /// Signature of the transaction which created the OpenOrders account, if it was
+    /// created for this batch.
+    pub open_orders_signature: Option<String>,
+    pub orders: Vec<BatchOrderOutput>,
+    /// Set when the OpenOrders account was re-adopted.
+    pub warnings: Vec<String>,
+}
+
+impl Render for BatchOutput {
+    fn render_table(&self) {
+        for warning in self.warnings.iter() {
+            println!("Warning: {}", warning);
+        }
+
+        if let (Some(open_orders), Some(signature)) =
+            (&self.open_orders, &self.open_orders_signature)
+        {
+            println!(
+                "Open orders account {} is created with signature {}",
+                open_orders, signature
+            );
+        }
+
+        for order in self.orders.iter() {
+            let error = order.error.as_deref().unwrap_or_default();
+            match (order.status, order.client_order_id, &order.signature) {
+                (BatchOrderStatus::Placed, Some(client_order_id), Some(signature)) => println!(
+                    "Order #{}: placed with client order id {}, signature {}",
+                    order.index, client_order_id, signature
+                ),
+                (BatchOrderStatus::Failed, _, _) => {
+                    println!("Order #{}: failed, {}", order.index, error)
+                }
+                _ => println!("Order #{}: rejected, {}", order.index, error),
+            }
+        }
+
+        if self.open_orders.is_none() {
+            println!("There are no valid orders to place");
+        }
+    }
+}
+
+/// A transaction which cancelled orders by their client order ids.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct CancelOutput {
+    pub client_order_ids: Vec<u64>,
+    pub signature: String,
+    pub explorer_link: String,
+}
+
+impl Render for CancelOutput {
+    fn render_table(&self) {
+        println!(
+            "Cancelled orders {:?} with signature {}",
+            self.client_order_ids, self.signature
+        );
+        println!("    {}", self.explorer_link);
+    }
+}
+
+/// A transaction which moved the free balances of an OpenOrders account to the wallet.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct SettleOutput {
+    pub open_orders: String,
+    pub signature: String,
+    pub explorer_link: String,
+}
+
+impl Render for SettleOutput {
+    fn render_table(&self) {
+        println!(
+            "Settled funds of {} with signature {}",
+            self.open_orders, self.signature
+        );
+        println!("    {}", self.explorer_link);
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct SweepOutput {
+    pub cancelled: Vec<CancelOutput>,
+    /// Expired orders which were no longer in the book.
+    pub gone: Vec<u64>,
+    /// Error of the transaction which stopped the sweep, orders cancelled before it
+    /// are still listed.
+    pub error: Option<String>,
+}
+
+impl Render for SweepOutput {
+    fn render_table(&self) {
+        for cancel in self.cancelled.iter() {
+            cancel.render_table();
+        }
+
+        if let Some(error) = &self.error {
+            println!("{}", error);
+            return;
+        }
+
+        let cancelled: usize = self
+            .cancelled
+            .iter()
+            .map(|cancel| cancel.client_order_ids.len())
+            .sum();
+
+        if cancelled == 0 && self.gone.is_empty() {
+            println!("There are no expired orders");
+        } else {
+            println!(
+                "Cancelled {} expired order(s), {} were no longer in the book",
+                cancelled,
+                self.gone.len()
+            );
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RecoverOpenOrdersOutput {
+    /// OpenOrders accounts of the wallet on the market.
+    pub accounts: Vec<String>,
+    /// Account recorded in the config before the command ran.
+    pub recorded: Option<String>,
+    /// Account which is recorded in the config now, if it was changed.
+    pub adopted: Option<String>,
+}
+
+impl Render for RecoverOpenOrdersOutput {
+    fn render_table(&self) {
+        for account in self.accounts.iter() {
+            if Some(account) == self.recorded.as_ref() {
+                println!("{} (recorded)", account);
+            } else {
+                println!("{}", account);
+            }
+        }
+
+        if let Some(adopted) = &self.adopted {
+            println!("Open orders account {} is recorded in the config", adopted);
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct CloseOpenOrdersOutput {
+    pub open_orders: String,
+    pub destination: String,
+    pub signature: String,
+    pub explorer_link: String,
+}
+
+impl Render for CloseOpenOrdersOutput {
+    fn render_table(&self) {
+        println!(
+            "Open orders account {} is closed, rent is returned to {}",
+            self.open_orders, self.destination
+        );
+        println!("    {}", self.explorer_link);
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct CleanOutput {
+    /// Paths of the config files.
+    pub files: Vec<String>,
+    /// Open orders accounts which still hold orders or funds.
+    pub warnings: Vec<String>,
+    pub dry_run: bool,
+    /// Whether the files were removed, they are kept on a dry run or because of warnings.
+    pub removed: bool,
+    /// Path of the backup written before the files were removed.
+    pub backup: Option<String>,
+}
+
+impl Render for CleanOutput {
+    fn render_table(&self) {
+        println!("The following config files will be removed:");
+        for file in self.files.iter() {
+            println!("    {}", file);
+        }
+
+        for warning in self.warnings.iter() {
+            println!("Warning: {}", warning);
+        }
+
+        if let Some(backup) = &self.backup {
+            println!("Backup is written to {}", backup);
+        } else if !self.dry_run && !self.removed {
+            println!("Config files are kept, run clean with --force to remove them anyway");
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RestoreOutput {
+    pub archive: String,
+    /// Paths of the restored config files.
+    pub files: Vec<String>,
+}
+
+impl Render for RestoreOutput {
+    fn render_table(&self) {
+        println!("Restored from {}:", self.archive);
+        for file in self.files.iter() {
+            println!("    {}", file);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(price: u64, quantity: u64) -> BookOrder {
+        BookOrder {
+            order_id: String::from("1"),
+            client_order_id: 0,
+            price,
+            quantity,
+            owner: Pubkey::default().to_string(),
+        }
+    }
+
+    #[test]
+    fn test_book_levels_ok() {
+        let orders = vec![order(5, 10), order(5, 20), order(6, 1)];
+        let levels = book_levels(&orders);
+
+        assert_eq!(
+            levels,
+            vec![
+                BookLevel {
+                    price: 5,
+                    quantity: 30,
+                    orders: 2
+                },
+                BookLevel {
+                    price: 6,
+                    quantity: 1,
+                    orders: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_order_output_json_ok() {
+        let output = OrderOutput {
+            side: OrderSide::Lend,
+            order_type: OrderKind::PostOnly,
+            client_order_id: 42,
+            size: 10,
+            rate: 5,
+            signature: String::from("signature"),
+            explorer_link: explorer_link("signature", "http://localhost:8899"),
+            open_orders: Pubkey::default().to_string(),
+            open_orders_signature: None,
+            replaced_client_order_id: None,
+            expires_at: None,
+            outcome: None,
+            warnings: Vec::new(),
+        };
+
+        let json: serde_json::Value = serde_json::to_value(&output).unwrap();
+        assert_eq!(json["side"], "lend");
+        assert_eq!(json["order_type"], "post-only");
+        assert_eq!(json["client_order_id"], 42);
+    }
+
+    #[test]
+    fn test_batch_output_json_ok() {
+        let output = BatchOutput {
+            open_orders: Some(Pubkey::default().to_string()),
+            open_orders_signature: None,
+            orders: vec![
+                BatchOrderOutput {
+                    index: 1,
+                    status: BatchOrderStatus::Placed,
+                    client_order_id: Some(42),
+                    signature: Some(String::from("signature")),
+                    error: None,
+                },
+                BatchOrderOutput {
+                    index: 2,
+                    status: BatchOrderStatus::Rejected,
+                    client_order_id: None,
+                    signature: None,
+                    error: Some(String::from("insufficient balance")),
+                },
+            ],
+            warnings: Vec::new(),
+        };
+
+        let json: serde_json::Value = serde_json::to_value(&output).unwrap();
+        assert_eq!(json["orders"][0]["status"], "placed");
+        assert_eq!(json["orders"][1]["status"], "rejected");
+        assert_eq!(json["orders"][1]["error"], "insufficient balance");
+    }
+
+    #[test]
+    fn test_event_output_json_ok() {
+        let view = EventView::Fill {
+            side: serum_dex::matching::Side::Bid,
+            maker: true,
+            native_qty_paid: 10,
+            native_qty_received: 20,
+            native_fee_or_rebate: 1,
+            order_id: 5,
+            owner: [0; 4],
+            owner_slot: 0,
+            fee_tier: serum_dex::fees::FeeTier::Base,
+            client_order_id: std::num::NonZeroU64::new(42),
+        };
+
+        let event = EventOutput::new(7, view);
+        assert_eq!(event.owner(), Pubkey::default().to_string());
+
+        let json: serde_json::Value = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["kind"], "fill");
+        assert_eq!(json["side"], "borrow");
+        assert_eq!(json["seq_num"], 7);
+        assert_eq!(json["client_order_id"], 42);
+    }
+
+    #[test]
+    fn test_request_flag_names_ok() {
+        assert_eq!(
+            request_flag_names(0x01 | 0x04 | 0x08),
+            vec!["new-order", "bid", "post-only"]
+        );
+        assert_eq!(request_flag_names(0).is_empty(), true);
+    }
+}
@@ -0,0 +1,537 @@
+use std::str::FromStr;
+
+use anyhow::{format_err, Result};
+use debug_print::debug_println;
+use serum_dex::instruction::SelfTradeBehavior;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+};
+
+use crate::{
+    amend_order, cancel_order_by_client_order_ids, decode_events, explorer_link, fills_from_events,
+    get_open_order_pubkey, get_order_outcome, get_token_balance, load_checked_market_cache,
+    load_market_snapshot, load_open_orders, place_order, place_order_batch, read_open_order_pubkey,
+    resting_client_order_ids, settle_funds, summarize_open_orders, unix_timestamp, BatchOrder,
+    BatchOrderOutput, BatchOrderStatus, BatchOutput, FillHistory, JournalEntry, MarketPubkeys,
+    MarketSnapshot, OpenOrdersAccount, OpenOrdersSummary, OrderBuilder, OrderJournal, OrderKind,
+    OrderOutcomeOutput, OrderOutput, OrderSide, Orderbook, CONFIG_DIR,
+};
+
+/// An order to place through a `TradingSession`. `OrderRequest::new` sets up a limit
+/// order with a generated client order id and the default expiry.
+#[derive(Debug, Clone, Copy)]
+pub struct OrderRequest {
+    pub side: OrderSide,
+    pub size: u64,
+    pub rate: u64,
+    pub order_type: OrderKind,
+    pub self_trade_behavior: SelfTradeBehavior,
+    /// Client order id chosen by the user, a unique one is generated when `None`.
+    pub client_order_id: Option<u64>,
+    /// Unix timestamp until which the transaction has to land on-chain.
+    pub max_ts: Option<i64>,
+    /// Good-till-time recorded in the order journal for `sweep-expired`.
+    pub expires_at: Option<i64>,
+    /// Client order id of a resting order which is cancelled in the same transaction.
+    pub replace: Option<u64>,
+}
+
+impl OrderRequest {
+    pub fn new(side: OrderSide, size: u64, rate: u64) -> Self {
+        OrderRequest {
+            side,
+            size,
+            rate,
+            order_type: OrderKind::Limit,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            client_order_id: None,
+            max_ts: None,
+            expires_at: None,
+            replace: None,
+        }
+    }
+}
+
+/// Market and wallet state resolved once and kept for a whole interactive session, so
+/// every order doesn't reconnect and decode the market again.
+pub struct TradingSession<'a> {
+    pub client: &'a RpcClient,
+    pub program_id: Pubkey,
+    pub market_keys: MarketPubkeys,
+    pub owner: &'a Keypair,
+    pub coin_mint: Pubkey,
+    pub pc_mint: Pubkey,
+    pub coin_wallet: Pubkey,
+    pub pc_wallet: Pubkey,
+    pub coin_lot_size: u64,
+    pub pc_lot_size: u64,
+    /// OpenOrders account of the owner, created by the first order if there is none yet.
+    pub orders: Option<Pubkey>,
+}
+
+impl<'a> TradingSession<'a> {
+    /// Loads the market from the cache, checked against the market account. The wallets
+    /// are the owner's token accounts of the market's mints.
+    pub fn new(
+        client: &'a RpcClient,
+        program_id: &Pubkey,
+        market_pk: &Pubkey,
+        owner: &'a Keypair,
+    ) -> Result<Self> {
+        let market_cache = load_checked_market_cache(client, program_id, market_pk)?;
+        let coin_mint = Pubkey::from_str(&market_cache.coin_mint)?;
+        let pc_mint = Pubkey::from_str(&market_cache.pc_mint)?;
+
+        Ok(TradingSession {
+            client,
+            program_id: *program_id,
+            market_keys: market_cache.market_keys()?,
+            owner,
+            coin_mint,
+            pc_mint,
+            coin_wallet: spl_associated_token_account::get_associated_token_address(
+                &owner.pubkey(),
+                &coin_mint,
+            ),
+            pc_wallet: spl_associated_token_account::get_associated_token_address(
+                &owner.pubkey(),
+                &pc_mint,
+            ),
+            coin_lot_size: market_cache.coin_lot_size,
+            pc_lot_size: market_cache.pc_lot_size,
+            orders: read_open_order_pubkey(&owner.pubkey()).ok(),
+        })
+    }
+
+    /// Checks that the mint given for an order of the side is the one it's paid with,
+    /// the coin mint for lending and the pc mint for borrowing.
+    pub fn check_mint(&self, side: OrderSide, mint: &Pubkey) -> Result<()> {
+        let (expected_mint, kind) = match side {
+            OrderSide::Lend => (&self.coin_mint, "lending"),
+            OrderSide::Borrow => (&self.pc_mint, "borrowing"),
+        };
+
+        if mint != expected_mint {
+            return Err(format_err!(
+                "mint {} doesn't match the {} order, expected {}",
+                mint,
+                kind,
+                expected_mint
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Checks both mints given for a session which trades on both sides.
+    pub fn check_mints(&self, coin_mint: &Pubkey, pc_mint: &Pubkey) -> Result<()> {
+        self.check_mint(OrderSide::Lend, coin_mint)?;
+        self.check_mint(OrderSide::Borrow, pc_mint)
+    }
+
+    /// Fetches the accounts of the market together with the wallet's OpenOrders account.
+    pub fn snapshot(&self) -> Result<MarketSnapshot> {
+        load_market_snapshot(self.client, &self.market_keys, self.orders.as_ref())
+    }
+
+    pub fn orderbook(&self, snapshot: &MarketSnapshot) -> Result<Orderbook> {
+        snapshot.orderbook(None)
+    }
+
+    /// Summary of the OpenOrders account, `None` if the wallet has none yet.
+    pub fn open_orders(
+        &self,
+        snapshot: &MarketSnapshot,
+        orderbook: &Orderbook,
+    ) -> Result<Option<OpenOrdersSummary>> {
+        let (orders, open_orders) = match (self.orders, snapshot.open_orders()?) {
+            (Some(orders), Some(open_orders)) => (orders, open_orders),
+            _ => return Ok(None),
+        };
+
+        Ok(Some(summarize_open_orders(
+            &orders,
+            &open_orders,
+            orderbook,
+        )))
+    }
+
+    /// Returns the wallet's OpenOrders account, re-adopting or creating one when none is
+    /// recorded. A failed lookup is returned rather than creating another account next
+    /// to one the wallet may already own.
+    pub fn open_orders_account(&mut self) -> Result<OpenOrdersAccount> {
+        if let Some(address) = self.orders {
+            return Ok(OpenOrdersAccount {
+                address,
+                signature: None,
+                warnings: Vec::new(),
+            });
+        }
+
+        let account =
+            get_open_order_pubkey(self.client, &self.program_id, self.owner, &self.market_keys)
+                .map_err(|err| {
+                    format_err!(
+                        "unable to set up an open orders account, run recover-open-orders \
+                        if the wallet has one already: {}",
+                        err
+                    )
+                })?;
+
+        self.orders = Some(account.address);
+        Ok(account)
+    }
+
+    /// Places an order paid from the wallet's token account of the side, or replaces the
+    /// resting order given in the request, and records it in the order journal.
+    pub fn place(&mut self, request: OrderRequest) -> Result<OrderOutput> {
+        let wallet = match request.side {
+            OrderSide::Lend => self.coin_wallet,
+            OrderSide::Borrow => self.pc_wallet,
+        };
+
+        // the replaced order has to rest in the recorded OpenOrders account, which is
+        // checked before a new account could be set up
+        if let Some(replaced_client_order_id) = request.replace {
+            let orders = self
+                .orders
+                .ok_or_else(|| format_err!("there is no open orders account for this wallet"))?;
+            let open_orders = load_open_orders(self.client, &orders)?;
+
+            if !resting_client_order_ids(&open_orders).contains(&replaced_client_order_id) {
+                return Err(format_err!(
+                    "order with client order id {} is no longer in the book",
+                    replaced_client_order_id
+                ));
+            }
+        }
+
+        let mut journal = OrderJournal::load(CONFIG_DIR, &self.owner.pubkey())?;
+        let client_order_id = reserve_client_order_id(&mut journal, request.client_order_id)?;
+
+        let mut builder = self.order_builder(&request, client_order_id);
+
+        // funds released by the replaced order stay in the OpenOrders account and
+        // pay for the replacement, so the wallet balance alone can't be checked
+        if request.replace.is_none() {
+            builder = builder.balance(get_token_balance(self.client, &wallet)?);
+        }
+
+        let new_order = builder.build()?;
+
+        // set up only once the order is valid, so a rejected order doesn't create one
+        let account = self.open_orders_account()?;
+        let mut warnings = account.warnings;
+
+        let signature = match request.replace {
+            Some(replaced_client_order_id) => {
+                debug_println!("Replacing order {}...", replaced_client_order_id);
+                amend_order(
+                    self.client,
+                    &self.program_id,
+                    self.owner,
+                    &wallet,
+                    &self.market_keys,
+                    &account.address,
+                    replaced_client_order_id,
+                    new_order,
+                )?
+            }
+            None => {
+                debug_println!("Placing new order...");
+                place_order(
+                    self.client,
+                    &self.program_id,
+                    self.owner,
+                    &wallet,
+                    &self.market_keys,
+                    &mut Some(account.address),
+                    new_order,
+                )?
+            }
+        };
+
+        if let Some(replaced_client_order_id) = request.replace {
+            journal.mark_cancelled(&[replaced_client_order_id], Some(&signature.to_string()));
+        }
+
+        journal.record(JournalEntry {
+            client_order_id,
+            side: request.side,
+            size: request.size,
+            rate: request.rate,
+            signature: signature.to_string(),
+            timestamp: unix_timestamp().unwrap_or_default(),
+            expires_at: request.expires_at,
+            cancelled: false,
+            cancel_signature: None,
+        });
+
+        // the order is placed already, so failing to record it only warns
+        if let Err(err) = journal.save(CONFIG_DIR, &self.owner.pubkey()) {
+            warnings.push(format!(
+                "unable to record the order in the journal: {}",
+                err
+            ));
+        }
+
+        let outcome = get_order_outcome(
+            self.client,
+            &self.market_keys,
+            &account.address,
+            client_order_id,
+            self.coin_lot_size,
+        )
+        .map_err(|err| debug_println!("{:?}", err))
+        .ok();
+
+        Ok(OrderOutput {
+            side: request.side,
+            order_type: request.order_type,
+            client_order_id,
+            size: request.size,
+            rate: request.rate,
+            signature: signature.to_string(),
+            explorer_link: explorer_link(&signature.to_string(), &self.client.url()),
+            open_orders: account.address.to_string(),
+            open_orders_signature: account.signature.map(|signature| signature.to_string()),
+            replaced_client_order_id: request.replace,
+            expires_at: request.expires_at,
+            outcome: outcome.as_ref().map(OrderOutcomeOutput::from),
+            warnings,
+        })
+    }
+
+    /// Places the orders of a batch with as few transactions as possible and records the
+    /// placed ones in the order journal. Orders which don't pass validation against the
+    /// wallet balances left by the orders before them are reported as rejected and not sent.
+    pub fn place_batch(&mut self, batch: &[BatchOrder]) -> Result<BatchOutput> {
+        // every order of the batch is paid from the same wallets
+        let mut coin_balance = get_token_balance(self.client, &self.coin_wallet)?;
+        let mut pc_balance = get_token_balance(self.client, &self.pc_wallet)?;
+
+        let mut journal = OrderJournal::load(CONFIG_DIR, &self.owner.pubkey())?;
+        let mut new_orders = Vec::new();
+        let mut accepted = Vec::new();
+        let mut outputs = Vec::new();
+
+        for (index, order) in batch.iter().enumerate() {
+            let request = OrderRequest {
+                order_type: order.order_type,
+                client_order_id: order.client_id,
+                ..OrderRequest::new(order.side, order.size, order.rate)
+            };
+
+            let result = reserve_client_order_id(&mut journal, request.client_order_id).and_then(
+                |client_order_id| {
+                    let builder = self.order_builder(&request, client_order_id);
+                    let (balance, required) = match order.side {
+                        OrderSide::Lend => (&mut coin_balance, builder.native_coin_qty()?),
+                        OrderSide::Borrow => (&mut pc_balance, builder.max_native_pc_qty()?),
+                    };
+
+                    let new_order = builder.balance(*balance).build()?;
+                    *balance -= required;
+
+                    Ok(new_order)
+                },
+            );
+
+            match result {
+                Ok(new_order) => {
+                    accepted.push((index, new_order.client_order_id));
+                    new_orders.push(new_order);
+                }
+                Err(err) => outputs.push(BatchOrderOutput {
+                    index: index + 1,
+                    status: BatchOrderStatus::Rejected,
+                    client_order_id: order.client_id,
+                    signature: None,
+                    error: Some(err.to_string()),
+                }),
+            }
+        }
+
+        if new_orders.is_empty() {
+            return Ok(BatchOutput {
+                open_orders: None,
+                open_orders_signature: None,
+                orders: outputs,
+                warnings: Vec::new(),
+            });
+        }
+
+        let account = self.open_orders_account()?;
+        let mut warnings = account.warnings;
+
+        let results = place_order_batch(
+            self.client,
+            &self.program_id,
+            self.owner,
+            &self.coin_wallet,
+            &self.pc_wallet,
+            &self.market_keys,
+            &account.address,
+            new_orders,
+        );
+
+        let timestamp = unix_timestamp().unwrap_or_default();
+        for ((index, client_order_id), (_, result)) in accepted.into_iter().zip(results) {
+            match result {
+                Ok(signature) => {
+                    let order = &batch[index];
+                    journal.record(JournalEntry {
+                        client_order_id,
+                        side: order.side,
+                        size: order.size,
+                        rate: order.rate,
+                        signature: signature.to_string(),
+                        timestamp,
+                        expires_at: None,
+                        cancelled: false,
+                        cancel_signature: None,
+                    });
+
+                    outputs.push(BatchOrderOutput {
+                        index: index + 1,
+                        status: BatchOrderStatus::Placed,
+                        client_order_id: Some(client_order_id),
+                        signature: Some(signature.to_string()),
+                        error: None,
+                    });
+                }
+                Err(err) => outputs.push(BatchOrderOutput {
+                    index: index + 1,
+                    status: BatchOrderStatus::Failed,
+                    client_order_id: Some(client_order_id),
+                    signature: None,
+                    error: Some(err),
+                }),
+            }
+        }
+
+        // the orders are placed already, so failing to record them only warns
+        if let Err(err) = journal.save(CONFIG_DIR, &self.owner.pubkey()) {
+            warnings.push(format!(
+                "unable to record the orders in the journal: {}",
+                err
+            ));
+        }
+
+        outputs.sort_by_key(|output| output.index);
+        Ok(BatchOutput {
+            open_orders: Some(account.address.to_string()),
+            open_orders_signature: account.signature.map(|signature| signature.to_string()),
+            orders: outputs,
+            warnings,
+        })
+    }
+
+    /// Builds the order of a request without checking it against a balance.
+    fn order_builder(&self, request: &OrderRequest, client_order_id: u64) -> OrderBuilder {
+        let builder = OrderBuilder::new(request.side.into())
+            .price(request.rate)
+            .size(request.size)
+            .client_order_id(client_order_id)
+            .order_type(request.order_type.into())
+            .self_trade_behavior(request.self_trade_behavior)
+            .lot_sizes(self.coin_lot_size, self.pc_lot_size);
+
+        match request.max_ts {
+            Some(max_ts) => builder.max_ts(max_ts),
+            None => builder,
+        }
+    }
+
+    /// Cancels up to 8 orders by client order id and marks them cancelled in the journal.
+    pub fn cancel(&self, client_order_ids: &[u64]) -> Result<Signature> {
+        let orders = self
+            .orders
+            .ok_or_else(|| format_err!("there is no open orders account for this wallet"))?;
+
+        if client_order_ids.is_empty() || client_order_ids.len() > 8 {
+            return Err(format_err!(
+                "between 1 and 8 orders can be cancelled at once, got {}",
+                client_order_ids.len()
+            ));
+        }
+
+        let mut ids = [0; 8];
+        ids[..client_order_ids.len()].copy_from_slice(client_order_ids);
+
+        let signature = cancel_order_by_client_order_ids(
+            self.client,
+            self.owner,
+            &self.program_id,
+            &self.market_keys,
+            &orders,
+            ids,
+        )?;
+
+        let mut journal = OrderJournal::load(CONFIG_DIR, &self.owner.pubkey())?;
+        journal.mark_cancelled(client_order_ids, Some(&signature.to_string()));
+        journal.save(CONFIG_DIR, &self.owner.pubkey())?;
+
+        Ok(signature)
+    }
+
+    /// Moves the free balances of the OpenOrders account back to the wallet.
+    pub fn settle(&self) -> Result<Signature> {
+        let orders = self
+            .orders
+            .ok_or_else(|| format_err!("there is no open orders account for this wallet"))?;
+
+        settle_funds(
+            self.client,
+            &self.program_id,
+            self.owner,
+            &self.market_keys,
+            &orders,
+            &self.coin_wallet,
+            &self.pc_wallet,
+        )
+    }
+
+    /// Merges the wallet's fills left in the event queue of the snapshot into its fill
+    /// history and returns the history together with the number of fills which were added.
+    pub fn fill_history(&self, snapshot: &MarketSnapshot) -> Result<(FillHistory, usize)> {
+        let mut history =
+            FillHistory::load(CONFIG_DIR, &self.owner.pubkey(), &self.market_keys.market)?;
+
+        let orders = match self.orders {
+            Some(orders) => orders,
+            None => return Ok((history, 0)),
+        };
+
+        let (header, events) = snapshot.event_queue()?;
+        let events = decode_events(&self.market_keys, header, &events, None)?;
+        let fills = fills_from_events(
+            &events.events,
+            &orders,
+            self.coin_lot_size,
+            self.pc_lot_size,
+            unix_timestamp()?,
+        );
+
+        let added = history.merge(fills);
+        if added > 0 {
+            history.save(CONFIG_DIR, &self.owner.pubkey(), &self.market_keys.market)?;
+        }
+
+        Ok((history, added))
+    }
+}
+
+/// Reserves the client order id chosen for an order, or generates one.
+fn reserve_client_order_id(
+    journal: &mut OrderJournal,
+    client_order_id: Option<u64>,
+) -> Result<u64> {
+    match client_order_id {
+        Some(client_order_id) => journal.use_client_order_id(client_order_id),
+        None => journal.next_client_order_id(),
+    }
+}
@@ -0,0 +1,363 @@
+use std::path::Path;
+
+use anyhow::{format_err, Result};
+use debug_print::debug_println;
+use rustyline::{
+    completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter,
+    validate::Validator, Context, Editor, Helper,
+};
+
+use crate::{
+    explorer_link, render, render_error, CancelOutput, FetchOutput, FillsOutput, OpenOrdersOutput,
+    OrderKind, OrderRequest, OrderSide, OutputFormat, SettleOutput, TradingSession, CONFIG_DIR,
+    SHELL_HISTORY,
+};
+
+const COMMANDS: [&str; 9] = [
+    "lend", "borrow", "book", "orders", "cancel", "settle", "fills", "help", "exit",
+];
+
+const ORDER_TYPES: [&str; 3] = ["limit", "ioc", "post-only"];
+
+const HELP: &str = "Commands:
+    lend <SIZE> @ <RATE> [limit|ioc|post-only]    place an order to lend
+    borrow <SIZE> @ <RATE> [limit|ioc|post-only]  place an order to borrow
+    book [DEPTH]                                  show the orderbook
+    orders                                        show the open orders account
+    cancel <CLIENT_ORDER_ID>...                   cancel up to 8 orders
+    settle                                        settle free funds to the wallet
+    fills                                         show the fill history
+    help                                          show this help
+    exit                                          leave the shell";
+
+/// A command entered in the interactive shell.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShellCommand {
+    Order {
+        side: OrderSide,
+        size: u64,
+        rate: u64,
+        order_type: OrderKind,
+    },
+    Book {
+        depth: Option<usize>,
+    },
+    Orders,
+    Cancel {
+        client_order_ids: Vec<u64>,
+    },
+    Settle,
+    Fills,
+    Help,
+    Exit,
+}
+
+/// Parses a line of the shell, `None` is returned for empty lines. Orders are written as
+/// `lend 100 @ 5 post-only`, where the `@` and the order type are optional.
+pub fn parse_shell_command(line: &str) -> Result<Option<ShellCommand>> {
+    let line = line.replace('@', " @ ");
+    let words: Vec<&str> = line.split_whitespace().collect();
+
+    let (command, args) = match words.split_first() {
+        Some((command, args)) => (command.to_lowercase(), args),
+        None => return Ok(None),
+    };
+
+    let no_args = |command: ShellCommand| {
+        if args.is_empty() {
+            Ok(command)
+        } else {
+            Err(format_err!("{} takes no arguments", words[0]))
+        }
+    };
+
+    let command = match command.as_str() {
+        "lend" | "borrow" => {
+            let side = if command == "lend" {
+                OrderSide::Lend
+            } else {
+                OrderSide::Borrow
+            };
+            parse_order(side, args)?
+        }
+        "book" => {
+            let depth = match args {
+                [] => None,
+                [depth] => Some(
+                    depth
+                        .parse::<usize>()
+                        .map_err(|_| format_err!("invalid depth {}", depth))?,
+                ),
+                _ => return Err(format_err!("usage: book [DEPTH]")),
+            };
+            ShellCommand::Book { depth }
+        }
+        "cancel" => {
+            let client_order_ids = args
+                .iter()
+                .map(|id| {
+                    id.parse::<u64>()
+                        .map_err(|_| format_err!("invalid client order id {}", id))
+                })
+                .collect::<Result<Vec<u64>>>()?;
+
+            if client_order_ids.is_empty() {
+                return Err(format_err!("usage: cancel <CLIENT_ORDER_ID>..."));
+            }
+            ShellCommand::Cancel { client_order_ids }
+        }
+        "orders" => no_args(ShellCommand::Orders)?,
+        "settle" => no_args(ShellCommand::Settle)?,
+        "fills" => no_args(ShellCommand::Fills)?,
+        "help" | "?" => ShellCommand::Help,
+        "exit" | "quit" => ShellCommand::Exit,
+        other => return Err(format_err!("unknown command {}, try help", other)),
+    };
+
+    Ok(Some(command))
+}
+
+fn parse_order(side: OrderSide, args: &[&str]) -> Result<ShellCommand> {
+    let usage = || format_err!("usage: lend|borrow <SIZE> @ <RATE> [limit|ioc|post-only]");
+
+    let args: Vec<&str> = args.iter().copied().filter(|arg| *arg != "@").collect();
+    let (size, rate, order_type) = match args.as_slice() {
+        [size, rate] => (size, rate, None),
+        [size, rate, order_type] => (size, rate, Some(*order_type)),
+        _ => return Err(usage()),
+    };
+
+    let order_type = match order_type {
+        Some(order_type) => order_type.parse::<OrderKind>()?,
+        None => OrderKind::Limit,
+    };
+
+    Ok(ShellCommand::Order {
+        side,
+        size: size
+            .parse::<u64>()
+            .map_err(|_| format_err!("invalid size {}", size))?,
+        rate: rate
+            .parse::<u64>()
+            .map_err(|_| format_err!("invalid rate {}", rate))?,
+        order_type,
+    })
+}
+
+/// Completes command names and the order type of orders.
+struct ShellHelper;
+
+impl Completer for ShellHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        let start = line.rfind(char::is_whitespace).map_or(0, |index| index + 1);
+        let word = &line[start..];
+        let previous: Vec<&str> = line[..start].split_whitespace().collect();
+
+        let candidates: &[&str] = match previous.first() {
+            None => &COMMANDS,
+            Some(&"lend") | Some(&"borrow") if previous.len() >= 3 => &ORDER_TYPES,
+            _ => &[],
+        };
+
+        Ok((
+            start,
+            candidates
+                .iter()
+                .filter(|candidate| candidate.starts_with(word))
+                .map(|candidate| candidate.to_string())
+                .collect(),
+        ))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+/// Reads commands until `exit` or end of input and runs them against the session.
+/// The command history is kept in the config directory between sessions.
+pub fn run_shell(session: &mut TradingSession, format: OutputFormat) -> Result<()> {
+    let history = Path::new(CONFIG_DIR).join(SHELL_HISTORY);
+
+    let mut editor = Editor::<ShellHelper>::new()?;
+    editor.set_helper(Some(ShellHelper));
+    if editor.load_history(&history).is_err() {
+        debug_println!("No shell history found");
+    }
+
+    // the banner would break the JSON printed for every command
+    if format == OutputFormat::Table {
+        println!("Connected to market {}", session.market_keys.market);
+        println!("Type help to list the commands");
+    }
+
+    loop {
+        let line = match editor.readline("serum> ") {
+            Ok(line) => line,
+            // ctrl-c discards the line, ctrl-d leaves the shell
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err.into()),
+        };
+
+        if !line.trim().is_empty() {
+            editor.add_history_entry(line.trim());
+        }
+
+        match parse_shell_command(&line) {
+            Ok(Some(ShellCommand::Exit)) => break,
+            Ok(Some(command)) => {
+                if let Err(err) = run_shell_command(session, command, format) {
+                    render_error(format, err);
+                }
+            }
+            Ok(None) => {}
+            Err(err) => render_error(format, err),
+        }
+    }
+
+    if let Err(err) = editor.save_history(&history) {
+        debug_println!("{:?}", err);
+    }
+
+    Ok(())
+}
+
+fn run_shell_command(
+    session: &mut TradingSession,
+    command: ShellCommand,
+    format: OutputFormat,
+) -> Result<()> {
+    match command {
+        ShellCommand::Order {
+            side,
+            size,
+            rate,
+            order_type,
+        } => {
+            let output = session.place(OrderRequest {
+                order_type,
+                ..OrderRequest::new(side, size, rate)
+            })?;
+            render(format, &output)
+        }
+        ShellCommand::Book { depth } => {
+            let snapshot = session.snapshot()?;
+            let orderbook = session.orderbook(&snapshot)?;
+            let mut output = FetchOutput::new(&session.market_keys.market, &orderbook);
+            if let Some(depth) = depth {
+                output.truncate(depth);
+            }
+            render(format, &output)
+        }
+        ShellCommand::Orders => {
+            let snapshot = session.snapshot()?;
+            let orderbook = session.orderbook(&snapshot)?;
+            let summary = session
+                .open_orders(&snapshot, &orderbook)?
+                .ok_or_else(|| format_err!("there is no open orders account for this wallet"))?;
+            render(format, &OpenOrdersOutput::from(&summary))
+        }
+        ShellCommand::Cancel { client_order_ids } => {
+            let signature = session.cancel(&client_order_ids)?.to_string();
+            let output = CancelOutput {
+                client_order_ids,
+                explorer_link: explorer_link(&signature, &session.client.url()),
+                signature,
+            };
+            render(format, &output)
+        }
+        ShellCommand::Settle => {
+            let signature = session.settle()?.to_string();
+            let output = SettleOutput {
+                open_orders: session
+                    .orders
+                    .map(|orders| orders.to_string())
+                    .unwrap_or_default(),
+                explorer_link: explorer_link(&signature, &session.client.url()),
+                signature,
+            };
+            render(format, &output)
+        }
+        ShellCommand::Fills => {
+            let (history, new_fills) = session.fill_history(&session.snapshot()?)?;
+            let output = FillsOutput {
+                open_orders: session
+                    .orders
+                    .map(|orders| orders.to_string())
+                    .unwrap_or_default(),
+                new_fills,
+                csv: None,
+                fills: history.fills,
+            };
+            render(format, &output)
+        }
+        ShellCommand::Help => {
+            if format == OutputFormat::Table {
+                println!("{}", HELP);
+            }
+            Ok(())
+        }
+        ShellCommand::Exit => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_order_ok() {
+        let command = parse_shell_command("lend 100 @ 5").unwrap();
+        assert_eq!(
+            command,
+            Some(ShellCommand::Order {
+                side: OrderSide::Lend,
+                size: 100,
+                rate: 5,
+                order_type: OrderKind::Limit,
+            })
+        );
+
+        let command = parse_shell_command("borrow 50@3 post-only").unwrap();
+        assert_eq!(
+            command,
+            Some(ShellCommand::Order {
+                side: OrderSide::Borrow,
+                size: 50,
+                rate: 3,
+                order_type: OrderKind::PostOnly,
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_shell_command_err() {
+        assert_eq!(parse_shell_command("").unwrap(), None);
+        assert_eq!(parse_shell_command("lend 100").is_err(), true);
+        assert_eq!(parse_shell_command("lend 100 @ 5 fok").is_err(), true);
+        assert_eq!(parse_shell_command("cancel").is_err(), true);
+        assert_eq!(parse_shell_command("settle now").is_err(), true);
+        assert_eq!(parse_shell_command("trade").is_err(), true);
+        assert_eq!(
+            parse_shell_command("cancel 1 2").unwrap(),
+            Some(ShellCommand::Cancel {
+                client_order_ids: vec![1, 2]
+            })
+        );
+    }
+}
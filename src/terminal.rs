@@ -0,0 +1,623 @@
+use std::{
+    io::Stdout,
+    time::{Duration, Instant},
+};
+
+use anyhow::{format_err, Result};
+use crossterm::{
+    cursor::Show,
+    event::{self, Event as TermEvent, KeyCode, KeyEvent, KeyModifiers},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use solana_sdk::signer::Signer;
+use tui::{
+    backend::{Backend, CrosstermBackend},
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Row, Table},
+    Frame, Terminal,
+};
+
+use crate::{
+    get_token_balance, FetchOutput, FillRecord, OpenOrdersOutput, OrderKind, OrderRequest,
+    OrderSide, TradingSession,
+};
+
+/// Number of fills from the fill history shown in the fills pane.
+const RECENT_FILLS: usize = 20;
+
+/// Number of price levels shown on each side of the book.
+const BOOK_DEPTH: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    OrderForm,
+    OpenOrders,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FormField {
+    Side,
+    OrderType,
+    Size,
+    Rate,
+}
+
+impl FormField {
+    const ALL: [FormField; 4] = [
+        FormField::Side,
+        FormField::OrderType,
+        FormField::Size,
+        FormField::Rate,
+    ];
+}
+
+/// Order entry form, sizes and rates are kept as typed so they can be edited.
+#[derive(Debug, Clone)]
+struct OrderForm {
+    side: OrderSide,
+    order_type: OrderKind,
+    size: String,
+    rate: String,
+    field: usize,
+}
+
+impl Default for OrderForm {
+    fn default() -> Self {
+        OrderForm {
+            side: OrderSide::Lend,
+            order_type: OrderKind::Limit,
+            size: String::new(),
+            rate: String::new(),
+            field: 0,
+        }
+    }
+}
+
+impl OrderForm {
+    fn field(&self) -> FormField {
+        FormField::ALL[self.field]
+    }
+
+    fn toggle(&mut self) {
+        match self.field() {
+            FormField::Side => {
+                self.side = match self.side {
+                    OrderSide::Lend => OrderSide::Borrow,
+                    OrderSide::Borrow => OrderSide::Lend,
+                }
+            }
+            FormField::OrderType => {
+                self.order_type = match self.order_type {
+                    OrderKind::Limit => OrderKind::Ioc,
+                    OrderKind::Ioc => OrderKind::PostOnly,
+                    OrderKind::PostOnly => OrderKind::Limit,
+                }
+            }
+            FormField::Size | FormField::Rate => {}
+        }
+    }
+
+    fn input(&mut self) -> Option<&mut String> {
+        match self.field() {
+            FormField::Size => Some(&mut self.size),
+            FormField::Rate => Some(&mut self.rate),
+            FormField::Side | FormField::OrderType => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Balances {
+    lamports: u64,
+    coin: Option<u64>,
+    pc: Option<u64>,
+}
+
+struct TerminalApp<'a> {
+    session: TradingSession<'a>,
+    book: Option<FetchOutput>,
+    open_orders: Option<OpenOrdersOutput>,
+    fills: Vec<FillRecord>,
+    balances: Balances,
+    focus: Focus,
+    form: OrderForm,
+    selected: ListState,
+    status: String,
+    last_refresh: Instant,
+}
+
+/// Runs a full-screen trading UI showing the book, the wallet's open orders, recent
+/// fills and balances, with a form to place orders. The data is reloaded every
+/// `interval` and after every order or cancellation. Returns when the user quits.
+pub fn run_terminal(session: TradingSession, interval: Duration) -> Result<()> {
+    let mut app = TerminalApp {
+        session,
+        book: None,
+        open_orders: None,
+        fills: Vec::new(),
+        balances: Balances::default(),
+        focus: Focus::OrderForm,
+        form: OrderForm::default(),
+        selected: ListState::default(),
+        status: String::from("Loading..."),
+        last_refresh: Instant::now(),
+    };
+
+    // the terminal is restored however the UI exits, so errors and panics can be read
+    let guard = TerminalGuard::enter()?;
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        restore_terminal();
+        default_hook(info);
+    }));
+
+    let result = Terminal::new(CrosstermBackend::new(std::io::stdout()))
+        .map_err(anyhow::Error::from)
+        .and_then(|mut terminal| app.run(&mut terminal, interval));
+
+    let _ = std::panic::take_hook();
+    drop(guard);
+
+    result
+}
+
+/// Puts the terminal into raw mode and the alternate screen, and restores it when dropped.
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn enter() -> Result<Self> {
+        enable_raw_mode()?;
+        // created before entering the alternate screen, so raw mode is left if that fails
+        let guard = TerminalGuard;
+        execute!(std::io::stdout(), EnterAlternateScreen)?;
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+fn restore_terminal() {
+    let _ = disable_raw_mode();
+    let _ = execute!(std::io::stdout(), LeaveAlternateScreen, Show);
+}
+
+impl<'a> TerminalApp<'a> {
+    fn run(
+        &mut self,
+        terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+        interval: Duration,
+    ) -> Result<()> {
+        self.refresh();
+
+        loop {
+            let app = &*self;
+            terminal.draw(|frame| draw(frame, app))?;
+
+            let timeout = interval
+                .checked_sub(self.last_refresh.elapsed())
+                .unwrap_or_default();
+            if event::poll(timeout)? {
+                if let TermEvent::Key(key) = event::read()? {
+                    if !self.handle_key(key) {
+                        return Ok(());
+                    }
+                }
+            }
+
+            if self.last_refresh.elapsed() >= interval {
+                self.refresh();
+            }
+        }
+    }
+
+    /// Handles a key press, returns false when the UI should be closed.
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return false;
+        }
+
+        match (self.focus, key.code) {
+            (_, KeyCode::Esc) => return false,
+            (_, KeyCode::Tab) => {
+                self.focus = match self.focus {
+                    Focus::OrderForm => Focus::OpenOrders,
+                    Focus::OpenOrders => Focus::OrderForm,
+                };
+            }
+            (_, KeyCode::F(5)) => self.refresh(),
+            (Focus::OrderForm, KeyCode::Up) => {
+                self.form.field = self.form.field.saturating_sub(1);
+            }
+            (Focus::OrderForm, KeyCode::Down) => {
+                self.form.field = (self.form.field + 1).min(FormField::ALL.len() - 1);
+            }
+            (Focus::OrderForm, KeyCode::Left | KeyCode::Right | KeyCode::Char(' ')) => {
+                self.form.toggle();
+            }
+            (Focus::OrderForm, KeyCode::Char(c)) if c.is_ascii_digit() => {
+                if let Some(input) = self.form.input() {
+                    input.push(c);
+                }
+            }
+            (Focus::OrderForm, KeyCode::Backspace) => {
+                if let Some(input) = self.form.input() {
+                    input.pop();
+                }
+            }
+            (Focus::OrderForm, KeyCode::Enter) => {
+                self.status = match self.submit_order() {
+                    Ok(status) => status,
+                    Err(err) => format!("Order failed: {}", err),
+                };
+                self.refresh_quietly();
+            }
+            (Focus::OrderForm, KeyCode::Char('q')) => return false,
+            (Focus::OpenOrders, KeyCode::Char('q')) => return false,
+            (Focus::OpenOrders, KeyCode::Up) => self.select(-1),
+            (Focus::OpenOrders, KeyCode::Down) => self.select(1),
+            (Focus::OpenOrders, KeyCode::Char('r')) => self.refresh(),
+            (Focus::OpenOrders, KeyCode::Char('c') | KeyCode::Delete) => {
+                self.status = match self.cancel_selected() {
+                    Ok(status) => status,
+                    Err(err) => format!("Cancel failed: {}", err),
+                };
+                self.refresh_quietly();
+            }
+            _ => {}
+        }
+
+        true
+    }
+
+    fn select(&mut self, step: isize) {
+        let count = self
+            .open_orders
+            .as_ref()
+            .map(|open_orders| open_orders.orders.len())
+            .unwrap_or(0);
+
+        if count == 0 {
+            self.selected.select(None);
+            return;
+        }
+
+        let current = self.selected.selected().unwrap_or(0) as isize;
+        let next = (current + step).clamp(0, count as isize - 1);
+        self.selected.select(Some(next as usize));
+    }
+
+    fn refresh(&mut self) {
+        self.status = match self.load() {
+            Ok(()) => String::from("Updated"),
+            Err(err) => format!("Update failed: {}", err),
+        };
+    }
+
+    /// Reloads the data without replacing the status of the last order or cancellation.
+    fn refresh_quietly(&mut self) {
+        if let Err(err) = self.load() {
+            self.status += &format!(" (update failed: {})", err);
+        }
+    }
+
+    fn load(&mut self) -> Result<()> {
+        let session = &self.session;
+        self.last_refresh = Instant::now();
+
+        // the book, open orders and events are fetched at once so they are consistent
+        let snapshot = session.snapshot()?;
+        let orderbook = session.orderbook(&snapshot)?;
+        self.book = Some(FetchOutput::new(&session.market_keys.market, &orderbook));
+
+        self.balances = Balances {
+            lamports: session.client.get_balance(&session.owner.pubkey())?,
+            coin: get_token_balance(session.client, &session.coin_wallet).ok(),
+            pc: get_token_balance(session.client, &session.pc_wallet).ok(),
+        };
+
+        self.open_orders = session
+            .open_orders(&snapshot, &orderbook)?
+            .as_ref()
+            .map(OpenOrdersOutput::from);
+
+        // fills are merged into the fill history so they outlive the event queue
+        let (history, _) = session.fill_history(&snapshot)?;
+        let skipped = history.fills.len().saturating_sub(RECENT_FILLS);
+        self.fills = history.fills.into_iter().skip(skipped).rev().collect();
+
+        // keeps the selection within the orders left after a fill or cancellation
+        self.select(0);
+        Ok(())
+    }
+
+    fn submit_order(&mut self) -> Result<String> {
+        let size = self
+            .form
+            .size
+            .parse::<u64>()
+            .map_err(|_| format_err!("enter the size of the order"))?;
+        let rate = self
+            .form
+            .rate
+            .parse::<u64>()
+            .map_err(|_| format_err!("enter the rate of the order"))?;
+
+        let output = self.session.place(OrderRequest {
+            order_type: self.form.order_type,
+            ..OrderRequest::new(self.form.side, size, rate)
+        })?;
+
+        self.form.size.clear();
+        self.form.rate.clear();
+
+        let mut status = format!(
+            "Order {} placed: {}",
+            output.client_order_id,
+            short_signature(&output.signature)
+        );
+        for warning in output.warnings.iter() {
+            status.push_str(&format!(", warning: {}", warning));
+        }
+
+        Ok(status)
+    }
+
+    fn cancel_selected(&mut self) -> Result<String> {
+        let order = self
+            .selected
+            .selected()
+            .and_then(|index| self.open_orders.as_ref()?.orders.get(index))
+            .ok_or_else(|| format_err!("select an order to cancel"))?;
+
+        if order.client_order_id == 0 {
+            return Err(format_err!(
+                "order {} has no client order id and can't be cancelled here",
+                order.order_id
+            ));
+        }
+
+        let client_order_id = order.client_order_id;
+        let signature = self.session.cancel(&[client_order_id])?;
+
+        Ok(format!(
+            "Order {} cancelled: {}",
+            client_order_id,
+            short_signature(&signature.to_string())
+        ))
+    }
+}
+
+fn short_signature(signature: &str) -> String {
+    format!("{}...", &signature[..16.min(signature.len())])
+}
+
+fn side_name(side: OrderSide) -> &'static str {
+    match side {
+        OrderSide::Lend => "lend",
+        OrderSide::Borrow => "borrow",
+    }
+}
+
+fn pane<'b>(title: &'b str, focused: bool) -> Block<'b> {
+    let style = if focused {
+        Style::default().fg(Color::Yellow)
+    } else {
+        Style::default()
+    };
+
+    Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_style(style)
+}
+
+fn draw<B: Backend>(frame: &mut Frame<B>, app: &TerminalApp) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(10),
+            Constraint::Length(8),
+            Constraint::Length(1),
+        ])
+        .split(frame.size());
+
+    let top = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
+        .split(rows[0]);
+
+    let right = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(top[1]);
+
+    let bottom = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(rows[1]);
+
+    draw_book(frame, top[0], app);
+    draw_open_orders(frame, right[0], app);
+    draw_fills(frame, right[1], app);
+    draw_form(frame, bottom[0], app);
+    draw_balances(frame, bottom[1], app);
+
+    let help = match app.focus {
+        Focus::OrderForm => "up/down: field, left/right: change, enter: place, tab: orders",
+        Focus::OpenOrders => "up/down: select, c: cancel, r: refresh, tab: form",
+    };
+    let status = Paragraph::new(Spans::from(vec![
+        Span::styled(&app.status, Style::default().add_modifier(Modifier::BOLD)),
+        Span::raw(" | "),
+        Span::raw(help),
+        Span::raw(", q: quit"),
+    ]));
+    frame.render_widget(status, rows[2]);
+}
+
+fn draw_book<B: Backend>(frame: &mut Frame<B>, area: Rect, app: &TerminalApp) {
+    let mut rows = Vec::new();
+
+    if let Some(book) = &app.book {
+        // lending orders are listed above borrowing orders, best rates meet in the middle
+        for level in book.ask_levels.iter().take(BOOK_DEPTH).rev() {
+            rows.push(
+                Row::new(vec![
+                    level.price.to_string(),
+                    level.quantity.to_string(),
+                    level.orders.to_string(),
+                ])
+                .style(Style::default().fg(Color::Red)),
+            );
+        }
+
+        for level in book.bid_levels.iter().take(BOOK_DEPTH) {
+            rows.push(
+                Row::new(vec![
+                    level.price.to_string(),
+                    level.quantity.to_string(),
+                    level.orders.to_string(),
+                ])
+                .style(Style::default().fg(Color::Green)),
+            );
+        }
+    }
+
+    let table = Table::new(rows)
+        .header(
+            Row::new(vec!["Rate", "Size", "Orders"])
+                .style(Style::default().add_modifier(Modifier::BOLD)),
+        )
+        .block(pane("Book (lend / borrow)", false))
+        .widths(&[
+            Constraint::Percentage(40),
+            Constraint::Percentage(40),
+            Constraint::Percentage(20),
+        ]);
+
+    frame.render_widget(table, area);
+}
+
+fn draw_open_orders<B: Backend>(frame: &mut Frame<B>, area: Rect, app: &TerminalApp) {
+    let items: Vec<ListItem> = match &app.open_orders {
+        Some(open_orders) => open_orders
+            .orders
+            .iter()
+            .map(|order| {
+                let quantity = order
+                    .quantity
+                    .map(|quantity| quantity.to_string())
+                    .unwrap_or_else(|| String::from("not in book"));
+
+                ListItem::new(format!(
+                    "{:<6} rate {:<8} size {:<12} client id {}",
+                    side_name(order.side),
+                    order.rate,
+                    quantity,
+                    order.client_order_id
+                ))
+            })
+            .collect(),
+        None => vec![ListItem::new(
+            "There is no open orders account for this wallet",
+        )],
+    };
+
+    let list = List::new(items)
+        .block(pane("Open orders", app.focus == Focus::OpenOrders))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_symbol("> ");
+
+    // the selection is only shown while the pane has focus
+    let mut state = match app.focus {
+        Focus::OpenOrders => app.selected.clone(),
+        Focus::OrderForm => ListState::default(),
+    };
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+fn draw_fills<B: Backend>(frame: &mut Frame<B>, area: Rect, app: &TerminalApp) {
+    let items: Vec<ListItem> = app
+        .fills
+        .iter()
+        .map(|fill| {
+            ListItem::new(format!(
+                "{:<6} rate {:<8} size {:<10} fee {:<8} {}",
+                side_name(fill.side),
+                fill.rate,
+                fill.size,
+                fill.fee,
+                if fill.maker { "maker" } else { "taker" }
+            ))
+        })
+        .collect();
+
+    frame.render_widget(List::new(items).block(pane("Recent fills", false)), area);
+}
+
+fn draw_form<B: Backend>(frame: &mut Frame<B>, area: Rect, app: &TerminalApp) {
+    let focused = app.focus == Focus::OrderForm;
+
+    let lines: Vec<Spans> = FormField::ALL
+        .iter()
+        .enumerate()
+        .map(|(index, field)| {
+            let (label, value) = match field {
+                FormField::Side => ("Side", side_name(app.form.side).to_string()),
+                FormField::OrderType => ("Type", app.form.order_type.name().to_string()),
+                FormField::Size => ("Size", app.form.size.clone()),
+                FormField::Rate => ("Rate", app.form.rate.clone()),
+            };
+
+            let style = if focused && index == app.form.field {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+
+            Spans::from(vec![
+                Span::raw(format!("{:<6}", label)),
+                Span::styled(format!(" {:<16}", value), style),
+            ])
+        })
+        .collect();
+
+    frame.render_widget(
+        Paragraph::new(lines).block(pane("New order", focused)),
+        area,
+    );
+}
+
+fn draw_balances<B: Backend>(frame: &mut Frame<B>, area: Rect, app: &TerminalApp) {
+    let balance = |balance: Option<u64>| {
+        balance
+            .map(|balance| balance.to_string())
+            .unwrap_or_else(|| String::from("no token account"))
+    };
+
+    let mut lines = vec![
+        Spans::from(format!("SOL (lamports): {}", app.balances.lamports)),
+        Spans::from(format!("Coin wallet: {}", balance(app.balances.coin))),
+        Spans::from(format!("Pc wallet: {}", balance(app.balances.pc))),
+    ];
+
+    if let Some(open_orders) = &app.open_orders {
+        lines.push(Spans::from(format!(
+            "Open orders coin: free {}, locked {}",
+            open_orders.native_coin_free, open_orders.native_coin_locked
+        )));
+        lines.push(Spans::from(format!(
+            "Open orders pc: free {}, locked {}",
+            open_orders.native_pc_free, open_orders.native_pc_locked
+        )));
+    }
+
+    frame.render_widget(Paragraph::new(lines).block(pane("Balances", false)), area);
+}
@@ -1,6 +1,8 @@
+use std::collections::BTreeMap;
 use std::fs::{self, read_dir};
 use std::io::{Read, Write};
-use std::path::Path;
+use std::path::{Component, Path};
+use std::time::SystemTime;
 use std::{
     fs::{remove_dir, remove_file, File},
     io::BufReader,
@@ -137,6 +139,162 @@ pub fn remove_dir_and_files(dir_name: &str) -> Result<(), String> {
     Ok(())
 }
 
+pub fn list_files(dir_name: &str) -> Result<Vec<String>, String> {
+    if !Path::new(dir_name).is_dir() {
+        return Err("The given dir doesn't exist!".to_string());
+    }
+
+    let mut files = Vec::new();
+    for entry in read_dir(dir_name).map_err(|err| err.to_string())? {
+        let path = entry.map_err(|err| err.to_string())?.path();
+
+        if path.is_file() {
+            if let Some(file_name) = path.file_name().and_then(|name| name.to_str()) {
+                files.push(file_name.to_string());
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Writes the files of the dir into a single JSON archive inside the backup dir and
+/// returns the path of the archive. Archives are named after the time in nanoseconds,
+/// and an existing archive is never overwritten.
+pub fn backup_dir(dir_name: &str, backup_dir_name: &str) -> Result<String, String> {
+    let mut files = BTreeMap::new();
+    for file_name in list_files(dir_name)? {
+        let path = Path::new(dir_name).join(&file_name);
+        let content = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        files.insert(file_name, content);
+    }
+
+    let content = serde_json::to_string_pretty(&files).map_err(|err| err.to_string())?;
+    let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_err(|err| err.to_string())?
+        .as_nanos();
+    let file_name = format!("{}_{}.json", dir_name, timestamp);
+
+    let path = Path::new(backup_dir_name).join(&file_name);
+    if path.exists() {
+        return Err(format!("The backup {} already exists!", path.display()));
+    }
+
+    write_file(backup_dir_name, &file_name, &content)?;
+
+    Ok(path.to_string_lossy().to_string())
+}
+
+/// Returns the most recent archive written by `backup_dir` for the dir.
+pub fn latest_backup(dir_name: &str, backup_dir_name: &str) -> Option<String> {
+    let prefix = dir_name.to_string() + "_";
+
+    list_files(backup_dir_name)
+        .ok()?
+        .into_iter()
+        .filter(|file_name| file_name.starts_with(&prefix) && file_name.ends_with(".json"))
+        .max_by_key(|file_name| {
+            file_name[prefix.len()..file_name.len() - ".json".len()]
+                .parse::<u128>()
+                .unwrap_or_default()
+        })
+        .map(|file_name| {
+            Path::new(backup_dir_name)
+                .join(file_name)
+                .to_string_lossy()
+                .to_string()
+        })
+}
+
+/// Restores the files of an archive written by `backup_dir` into the dir and
+/// returns their names. The dir mustn't exist yet.
+pub fn restore_dir(archive: &str, dir_name: &str) -> Result<Vec<String>, String> {
+    if Path::new(dir_name).exists() {
+        return Err("The given dir already exists!".to_string());
+    }
+
+    let content = read_file(archive)?;
+    let files: BTreeMap<String, String> =
+        serde_json::from_str(&content).map_err(|err| err.to_string())?;
+
+    // every file has to land inside the dir, so only plain file names are accepted
+    for file_name in files.keys() {
+        let mut components = Path::new(file_name).components();
+        let is_plain = matches!(
+            (components.next(), components.next()),
+            (Some(Component::Normal(_)), None)
+        );
+
+        if !is_plain || file_name.contains('\\') {
+            return Err(format!(
+                "The backup holds an invalid file name {}",
+                file_name
+            ));
+        }
+    }
+
+    for (file_name, content) in files.iter() {
+        write_file(dir_name, file_name, content)?;
+    }
+
+    Ok(files.into_keys().collect())
+}
+
+pub fn unix_timestamp() -> Result<i64> {
+    Ok(SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_secs() as i64)
+}
+
+/// Link to the transaction on the Solana explorer for the cluster behind `url`.
+pub fn explorer_link(signature: &str, url: &str) -> String {
+    let base = format!("https://explorer.solana.com/tx/{}", signature);
+
+    if url.contains("mainnet-beta") {
+        base
+    } else if url.contains("devnet") {
+        base + "?cluster=devnet"
+    } else if url.contains("testnet") {
+        base + "?cluster=testnet"
+    } else {
+        let custom_url = url.replace(':', "%3A").replace('/', "%2F");
+        base + "?cluster=custom&customUrl=" + custom_url.as_str()
+    }
+}
+
+/// Websocket URL of the RPC node, which by convention listens on the port after the RPC port.
+pub fn websocket_url(url: &str) -> Result<String, String> {
+    let (scheme, rest) = if let Some(rest) = url.strip_prefix("https://") {
+        ("wss://", rest)
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        ("ws://", rest)
+    } else {
+        return Ok(url.to_string());
+    };
+
+    let (host, path) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, ""),
+    };
+
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) => match port.parse::<u16>() {
+            Ok(port) => {
+                let port = port
+                    .checked_add(1)
+                    .ok_or_else(|| format!("there is no port after the RPC port {}", port))?;
+                format!("{}:{}", name, port)
+            }
+            Err(_) => host.to_string(),
+        },
+        None => host.to_string(),
+    };
+
+    Ok(format!("{}{}{}", scheme, host, path))
+}
+
 pub fn is_initialized(dir_name: &str) -> bool {
     Path::new(dir_name).exists() && Path::new(dir_name).is_dir()
 }
@@ -177,6 +335,33 @@ mod tests {
         assert_eq!(result.is_err(), true);
     }
 
+    #[test]
+    fn test_explorer_link_ok() {
+        let link = explorer_link("sig", "https://api.devnet.solana.com");
+        assert_eq!(link, "https://explorer.solana.com/tx/sig?cluster=devnet");
+
+        let link = explorer_link("sig", "http://localhost:8899");
+        assert_eq!(
+            link,
+            "https://explorer.solana.com/tx/sig?cluster=custom&customUrl=http%3A%2F%2Flocalhost%3A8899"
+        );
+    }
+
+    #[test]
+    fn test_websocket_url_ok() {
+        let url = websocket_url("http://localhost:8899");
+        assert_eq!(url, Ok(String::from("ws://localhost:8900")));
+
+        let url = websocket_url("https://api.devnet.solana.com");
+        assert_eq!(url, Ok(String::from("wss://api.devnet.solana.com")));
+    }
+
+    #[test]
+    fn test_websocket_url_last_port_failed() {
+        let url = websocket_url("http://localhost:65535");
+        assert_eq!(url.is_err(), true);
+    }
+
     #[test]
     fn test_read_keypair_file_ok() {
         let outfile = "test_read_keypair_file_ok.json";
@@ -334,6 +519,71 @@ mod tests {
         assert_eq!(result.is_ok(), true);
     }
 
+    #[test]
+    fn test_backup_and_restore_dir_ok() {
+        let dir_name = "test_backup_and_restore_dir_ok";
+        let backup_dir_name = "test_backup_and_restore_dir_ok_backups";
+
+        let result = write_file(dir_name, "market_pubkey.json", "market_pubkey");
+        assert_eq!(result.is_ok(), true);
+
+        let result = write_file(dir_name, "url.json", "http://localhost:8899");
+        assert_eq!(result.is_ok(), true);
+
+        let first = backup_dir(dir_name, backup_dir_name).unwrap();
+        let archive = backup_dir(dir_name, backup_dir_name).unwrap();
+        assert_eq!(first != archive, true);
+        assert_eq!(Path::new(&first).exists(), true);
+        assert_eq!(
+            latest_backup(dir_name, backup_dir_name),
+            Some(archive.clone())
+        );
+
+        let result = restore_dir(&archive, dir_name);
+        assert_eq!(result.is_err(), true);
+
+        let result = remove_dir_and_files(dir_name);
+        assert_eq!(result.is_ok(), true);
+
+        let files = restore_dir(&archive, dir_name).unwrap();
+        assert_eq!(files, vec!["market_pubkey.json", "url.json"]);
+        assert_eq!(list_files(dir_name).unwrap(), files);
+
+        let path = dir_name.to_string() + "/url.json";
+        assert_eq!(read_file(path.as_str()).unwrap(), "http://localhost:8899");
+
+        let result = remove_dir_and_files(dir_name);
+        assert_eq!(result.is_ok(), true);
+
+        let result = remove_dir_and_files(backup_dir_name);
+        assert_eq!(result.is_ok(), true);
+    }
+
+    #[test]
+    fn test_restore_dir_invalid_file_name_failed() {
+        let dir_name = "test_restore_dir_invalid_file_name_failed";
+        let archive_dir_name = "test_restore_dir_invalid_file_name_failed_backups";
+
+        for file_name in [
+            "../escape.json",
+            "/tmp/escape.json",
+            "nested/url.json",
+            "..",
+        ] {
+            let files = BTreeMap::from([(file_name, "content")]);
+            let content = serde_json::to_string(&files).unwrap();
+            let result = write_file(archive_dir_name, "backup.json", &content);
+            assert_eq!(result.is_ok(), true);
+
+            let archive = archive_dir_name.to_string() + "/backup.json";
+            assert_eq!(restore_dir(&archive, dir_name).is_err(), true);
+            assert_eq!(Path::new(dir_name).exists(), false);
+        }
+
+        let result = remove_dir_and_files(archive_dir_name);
+        assert_eq!(result.is_ok(), true);
+    }
+
     #[test]
     fn test_remove_dir_and_files_failed() {
         let dir_name = "";